@@ -0,0 +1,49 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use my_engine::*;
+
+mod legacy_astar;
+
+
+///a generated dungeon plus a route between every pair of consecutive rooms
+fn setup() -> (Map, Vec<(usize, usize)>) {
+    let mut map = Map::new_map_rooms_and_corridors(&mut GameRng::seeded(1), 1, DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT);
+    map.populate_blocked();
+    let routes = map.rooms.windows(2)
+        .map(|pair| {
+            let (x1, y1) = pair[0].center();
+            let (x2, y2) = pair[1].center();
+            (map.xy_idx(x1, y1), map.xy_idx(x2, y2))
+        })
+        .collect();
+    (map, routes)
+}
+
+fn astar(c: &mut Criterion) {
+    let (map, routes) = setup();
+    let mut group = c.benchmark_group("astar room to room");
+
+    group.bench_function("legacy AStar", |b| b.iter(|| {
+        for (start, end) in routes.iter() {
+            black_box(legacy_astar::a_star_search(*start, *end, &map));
+        }
+    }));
+
+    group.bench_function("a_star_search", |b| b.iter(|| {
+        for (start, end) in routes.iter() {
+            black_box(a_star_search(*start, *end, &map));
+        }
+    }));
+
+    let mut pathfinder = AStarPathfinder::new(map.tiles.len());
+    let mut path = NavigationPath::new();
+    group.bench_function("reused AStarPathfinder", |b| b.iter(|| {
+        for (start, end) in routes.iter() {
+            black_box(pathfinder.search_into(*start, *end, &map, &mut path));
+        }
+    }));
+
+    group.finish();
+}
+
+criterion_group!(benches, astar);
+criterion_main!(benches);
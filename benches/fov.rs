@@ -0,0 +1,49 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use my_engine::*;
+
+
+///a generated dungeon plus the centre of every room to look from
+fn setup() -> (Map, Vec<Point>) {
+    let mut map = Map::new_map_rooms_and_corridors(&mut GameRng::seeded(1), 1, DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT);
+    map.populate_blocked();
+    let centers = map.rooms.iter()
+        .map(|room| {
+            let (x, y) = room.center();
+            Point::new(x, y)
+        })
+        .collect();
+    (map, centers)
+}
+
+fn fov(c: &mut Criterion) {
+    let (map, centers) = setup();
+    let mut group = c.benchmark_group("fov from every room");
+
+    for algorithm in [FovAlgorithm::Scanning, FovAlgorithm::Symmetric] {
+        group.bench_function(format!("{:?} into a new HashSet", algorithm), |b| b.iter(|| {
+            for center in centers.iter() {
+                black_box(algorithm.field_of_view_set(*center, 8, &map));
+            }
+        }));
+
+        let mut visible = VisibleTiles::new();
+        group.bench_function(format!("{:?} into reused VisibleTiles", algorithm), |b| b.iter(|| {
+            for center in centers.iter() {
+                algorithm.field_of_view_into(*center, 8, &map, &mut visible);
+                black_box(&visible);
+            }
+        }));
+
+        let mut cache = FovCache::new();
+        group.bench_function(format!("{:?} from FovCache", algorithm), |b| b.iter(|| {
+            for center in centers.iter() {
+                black_box(cache.get_or_compute(*center, 8, algorithm, &map));
+            }
+        }));
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, fov);
+criterion_main!(benches);
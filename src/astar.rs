@@ -1,28 +1,31 @@
-
 use std::cmp::Ordering;
-use std::collections::{BinaryHeap, HashMap};
+use std::collections::BinaryHeap;
 use std::convert::TryInto;
 
-use crate::Map;
+use crate::{BaseMap, Algorithm2D};
 
 /// Bail out if the A* search exceeds this many steps.
 const MAX_ASTAR_STEPS: usize = 65536;
 
+/// Hands every exit of a tile (and its cost) to the callback, lets one search loop serve both exit rules.
+type ExitFn<'a> = &'a dyn Fn(usize, &mut dyn FnMut(usize, f32));
+
 /// Request an A-Star search. The start and end are specified as index numbers (compatible with your
 /// BaseMap implementation), and it requires access to your map so as to call distance and exit determinations.
-pub fn a_star_search<T>(start: T, end: T, map: &Map) -> NavigationPath
+/// Sets up a fresh pathfinder each call; systems searching every turn should keep an `AStarPathfinder` around instead.
+pub fn a_star_search<T>(start: T, end: T, map: &dyn BaseMap) -> NavigationPath
 where
     T: TryInto<usize>,
 {
-    AStar::new(start.try_into().ok().unwrap(), end.try_into().ok().unwrap()).search(map)
+    AStarPathfinder::new(0).search(start.try_into().ok().unwrap(), end.try_into().ok().unwrap(), map)
 }
 
-
-pub fn a_star_search_cardinal<T>(start: T, end: T, map: &Map) -> NavigationPath
+/// Same as `a_star_search`, but only ever steps along one axis at a time.
+pub fn a_star_search_cardinal<T>(start: T, end: T, map: &dyn Algorithm2D) -> NavigationPath
 where
     T: TryInto<usize>,
 {
-    AStar::new(start.try_into().ok().unwrap(), end.try_into().ok().unwrap()).search_cardinal(map)
+    AStarPathfinder::new(0).search_cardinal(start.try_into().ok().unwrap(), end.try_into().ok().unwrap(), map)
 }
 
 
@@ -38,10 +41,9 @@ pub struct NavigationPath {
     pub steps: Vec<usize>,
 }
 
-#[allow(dead_code)]
 #[derive(Copy, Clone, Debug)]
 /// Node is an internal step inside the A-Star path (not exposed/public). Idx is the current cell,
-/// f is the total cost, g the neighbor cost, and h the heuristic cost.
+/// f is the total cost, g the cost of getting here from the start.
 /// See: https://en.wikipedia.org/wiki/A*_search_algorithm
 struct Node {
     idx: usize,
@@ -51,21 +53,26 @@ struct Node {
 
 impl PartialEq for Node {
     fn eq(&self, other: &Self) -> bool {
-        self.f == other.f
+        self.cmp(other) == Ordering::Equal
     }
 }
 
 impl Eq for Node {}
 
 impl Ord for Node {
+    /// BinaryHeap pops the greatest, so lowest f wins. On equal f the node that got further
+    /// (higher g) goes first, which keeps the search heading at the goal instead of fanning out;
+    /// the index settles anything left so results never depend on heap internals.
     fn cmp(&self, b: &Self) -> Ordering {
-        b.f.partial_cmp(&self.f).unwrap()
+        b.f.total_cmp(&self.f)
+            .then_with(|| self.g.total_cmp(&b.g))
+            .then_with(|| b.idx.cmp(&self.idx))
     }
 }
 
 impl PartialOrd for Node {
     fn partial_cmp(&self, b: &Self) -> Option<Ordering> {
-        b.f.partial_cmp(&self.f)
+        Some(self.cmp(b))
     }
 }
 
@@ -80,136 +87,142 @@ impl NavigationPath {
     }
 }
 
-/// Private structure for calculating an A-Star navigation path.
-struct AStar {
-    start: usize,
-    end: usize,
+/// Reusable A-Star search. Keeps flat per-tile arrays sized to the biggest map it has seen and
+/// tags each entry with the search that wrote it, so starting a new search costs nothing and,
+/// once warmed up, searching doesn't allocate (as long as the map overrides `BaseMap::for_each_exit`).
+pub struct AStarPathfinder {
+    /// cheapest known cost from the start, valid where `seen[idx] == generation`
+    g: Vec<f32>,
+    /// tile we reached this one from, valid where `seen[idx] == generation`
+    parent: Vec<usize>,
+    seen: Vec<u32>,
+    closed: Vec<u32>,
+    generation: u32,
     open_list: BinaryHeap<Node>,
-    closed_list: HashMap<usize, f32>,
-    parents: HashMap<usize, (usize, f32)>, // (index, cost)
-    step_counter: usize,
 }
 
-impl AStar {
-    /// Creates a new path, with specified starting and ending indices.
-    fn new(start: usize, end: usize) -> AStar {
-        let mut open_list: BinaryHeap<Node> = BinaryHeap::new();
-        open_list.push(Node {
-            idx: start,
-            f: 0.0,
-            g: 0.0,
-        });
+impl Default for AStarPathfinder {
+    fn default() -> Self {
+        Self::new(0)
+    }
+}
 
-        AStar {
-            start,
-            end,
-            open_list,
-            parents: HashMap::new(),
-            closed_list: HashMap::new(),
-            step_counter: 0,
+impl AStarPathfinder {
+    /// Creates a pathfinder with room for `size` tiles; it grows by itself if a map turns out bigger.
+    pub fn new(size: usize) -> AStarPathfinder {
+        AStarPathfinder {
+            g: vec![0.0; size],
+            parent: vec![0; size],
+            seen: vec![0; size],
+            closed: vec![0; size],
+            generation: 0,
+            open_list: BinaryHeap::new(),
         }
     }
 
-    /// Wrapper to the BaseMap's distance function.
-    fn distance_to_end(&self, idx: usize, map: &Map) -> f32 {
-        map.get_pathing_distance(idx, self.end)
+    /// Finds the cheapest path from `start` to `end`.
+    pub fn search(&mut self, start: usize, end: usize, map: &dyn BaseMap) -> NavigationPath {
+        let mut path = NavigationPath::new();
+        self.search_into(start, end, map, &mut path);
+        path
     }
 
-    /// Adds a successor; if we're at the end, marks success.
-    fn add_successor(&mut self, q: Node, idx: usize, cost: f32, map: &Map) {
-        let distance_to_end = self.distance_to_end(idx, map);
-        let s = Node {
-            idx,
-            f: q.g + cost + distance_to_end,
-            g: cost,
-        };
-
-        // If a node with the same position as successor is in the open list with a lower f, skip add
-        let mut should_add = true;
-        if let Some(e) = self.parents.get(&idx) {
-            if e.1 < s.g {
-                should_add = false;
+    /// Same as `search`, but only ever steps along one axis at a time.
+    pub fn search_cardinal(&mut self, start: usize, end: usize, map: &dyn Algorithm2D) -> NavigationPath {
+        let mut path = NavigationPath::new();
+        self.run(start, end, map, &mut path, &|idx, f| {
+            for (exit, cost) in map.get_available_cardinal_exits(idx) {
+                f(exit, cost);
             }
-        }
+        });
+        path
+    }
 
-        // If a node with the same position as successor is in the closed list, with a lower f, skip add
-        if should_add && self.closed_list.contains_key(&idx) {
-            should_add = false;
-        }
+    /// Like `search`, writing into an existing path so its step buffer gets reused. Returns `path.success`.
+    pub fn search_into(&mut self, start: usize, end: usize, map: &dyn BaseMap, path: &mut NavigationPath) -> bool {
+        self.run(start, end, map, path, &|idx, f| map.for_each_exit(idx, f))
+    }
 
-        if should_add {
-            self.open_list.push(s);
-            self.parents.insert(idx, (q.idx, s.g));
+    /// Starts a new search: bumps the generation so every per-tile entry from before reads as stale.
+    fn reset(&mut self) {
+        self.generation = self.generation.wrapping_add(1);
+        if self.generation == 0 {
+            // wrapped around, stale entries could now look current
+            self.seen.iter_mut().for_each(|s| *s = 0);
+            self.closed.iter_mut().for_each(|c| *c = 0);
+            self.generation = 1;
         }
+        self.open_list.clear();
     }
 
-    /// Helper function to unwrap a path once we've found the end-point.
-    fn found_it(&self) -> NavigationPath {
-        let mut result = NavigationPath::new();
-        result.success = true;
-        result.destination = self.end;
-
-        result.steps.push(self.end);
-        let mut current = self.end;
-        while current != self.start {
-            let parent = self.parents[&current];
-            result.steps.insert(0, parent.0);
-            current = parent.0;
+    fn ensure_size(&mut self, idx: usize) {
+        if idx >= self.seen.len() {
+            let size = (idx + 1).max(self.seen.len() * 2);
+            self.g.resize(size, 0.0);
+            self.parent.resize(size, 0);
+            self.seen.resize(size, 0);
+            self.closed.resize(size, 0);
         }
-
-        result
     }
 
-    /// Performs an A-Star search
-    fn search(&mut self, map: &Map) -> NavigationPath {
-        let result = NavigationPath::new();
-        while !self.open_list.is_empty() && self.step_counter < MAX_ASTAR_STEPS {
-            self.step_counter += 1;
-
-            // Pop Q off of the list
-            let q = self.open_list.pop().unwrap();
-            if q.idx == self.end {
-                let success = self.found_it();
-                return success;
+    fn run(&mut self, start: usize, end: usize, map: &dyn BaseMap, path: &mut NavigationPath,
+           exits: ExitFn) -> bool {
+        self.reset();
+        path.destination = end;
+        path.success = false;
+        path.steps.clear();
+
+        let generation = self.generation;
+        self.ensure_size(start.max(end));
+        self.g[start] = 0.0;
+        self.parent[start] = start;
+        self.seen[start] = generation;
+        self.open_list.push(Node { idx: start, f: map.get_pathing_distance(start, end), g: 0.0 });
+
+        let mut step_counter = 0;
+        while let Some(q) = self.open_list.pop() {
+            if step_counter >= MAX_ASTAR_STEPS {
+                break;
             }
+            step_counter += 1;
 
-            // Generate successors
-            map.get_available_exits(q.idx)
-                .iter()
-                .for_each(|s| self.add_successor(q, s.0, s.1, map));
-
-            if self.closed_list.contains_key(&q.idx) {
-                self.closed_list.remove(&q.idx);
+            // skip stale entries: already expanded, or pushed before a cheaper route turned up
+            if self.closed[q.idx] == generation || q.g > self.g[q.idx] {
+                continue;
+            }
+            if q.idx == end {
+                self.found_it(start, end, path);
+                return true;
             }
-            self.closed_list.insert(q.idx, q.f);
+            self.closed[q.idx] = generation;
+
+            let mut successors = |idx: usize, cost: f32| {
+                self.ensure_size(idx);
+                if self.closed[idx] == generation {
+                    return;
+                }
+                let g = q.g + cost;
+                if self.seen[idx] != generation || g < self.g[idx] {
+                    self.seen[idx] = generation;
+                    self.g[idx] = g;
+                    self.parent[idx] = q.idx;
+                    self.open_list.push(Node { idx, f: g + map.get_pathing_distance(idx, end), g });
+                }
+            };
+            exits(q.idx, &mut successors);
         }
-        result
+        false
     }
 
-    fn search_cardinal(&mut self, map: &Map) -> NavigationPath {
-        let result = NavigationPath::new();
-        while !self.open_list.is_empty() && self.step_counter < MAX_ASTAR_STEPS {
-            self.step_counter += 1;
-
-            // Pop Q off of the list
-            let q = self.open_list.pop().unwrap();
-            if q.idx == self.end {
-                let success = self.found_it();
-                return success;
-            }
-
-            // Generate successors
-            map.get_available_cardinal_exits(q.idx)
-                .iter()
-                .for_each(|s| self.add_successor(q, s.0, s.1, map));
-
-            if self.closed_list.contains_key(&q.idx) {
-                self.closed_list.remove(&q.idx);
-            }
-            self.closed_list.insert(q.idx, q.f);
+    /// Helper function to unwrap a path once we've found the end-point.
+    fn found_it(&self, start: usize, end: usize, path: &mut NavigationPath) {
+        path.success = true;
+        let mut current = end;
+        path.steps.push(current);
+        while current != start {
+            current = self.parent[current];
+            path.steps.push(current);
         }
-        result
+        path.steps.reverse();
     }
 }
-
-
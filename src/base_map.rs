@@ -0,0 +1,56 @@
+use super::Point;
+
+
+/// What pathfinding and field of view need to know about a map. Tiles are addressed by index,
+/// the map decides how indices are laid out.
+pub trait BaseMap {
+    ///true if the tile blocks line of sight
+    fn is_opaque(&self, idx: usize) -> bool;
+
+    ///tiles you can step to from `idx`, with the cost of the step
+    fn get_available_exits(&self, idx: usize) -> Vec<(usize, f32)>;
+
+    ///calls `f` with every exit of `idx`. Pathfinding uses this in its inner loop,
+    ///override it to skip the Vec that `get_available_exits` allocates
+    fn for_each_exit(&self, idx: usize, f: &mut dyn FnMut(usize, f32)) {
+        for (exit, cost) in self.get_available_exits(idx) {
+            f(exit, cost);
+        }
+    }
+
+    ///heuristic distance between two tiles, used by A* to decide what to try first
+    fn get_pathing_distance(&self, idx1: usize, idx2: usize) -> f32;
+}
+
+/// A `BaseMap` laid out as a rectangular grid, indexed row by row.
+pub trait Algorithm2D: BaseMap {
+    ///width and height of the grid
+    fn dimensions(&self) -> Point;
+
+    fn point2d_to_index(&self, pt: Point) -> usize {
+        let bounds = self.dimensions();
+        ((pt.y * bounds.x) + pt.x) as usize
+    }
+
+    fn index_to_point2d(&self, idx: usize) -> Point {
+        let bounds = self.dimensions();
+        Point::new(idx as i32 % bounds.x, idx as i32 / bounds.x)
+    }
+
+    fn in_bounds(&self, pos: Point) -> bool {
+        let bounds = self.dimensions();
+        pos.x >= 0 && pos.x < bounds.x && pos.y >= 0 && pos.y < bounds.y
+    }
+
+    ///like `get_available_exits` but only the steps that move along one axis
+    fn get_available_cardinal_exits(&self, idx: usize) -> Vec<(usize, f32)> {
+        let here = self.index_to_point2d(idx);
+        self.get_available_exits(idx)
+            .into_iter()
+            .filter(|(exit, _cost)| {
+                let there = self.index_to_point2d(*exit);
+                (there.x == here.x) != (there.y == here.y)
+            })
+            .collect()
+    }
+}
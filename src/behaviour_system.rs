@@ -0,0 +1,294 @@
+use rand::Rng;
+use specs::prelude::*;
+
+use super::{Viewshed, Position, Map, Monster, AiState, Point, WantsToMelee, DijkstraCache, AStarPathfinder, MovementProfile, BaseMap,
+            CombatStats, BlocksTile, Door, Renderable, Energy, MyTurn, ActionCost, GameRng, Behaviour, BehaviourLibrary, BehaviourTree,
+            Agent, Condition, Action, Target, InBackpack, ProvidesHealing, Name, EventLog, take_step, field_from, find_foes, Foes, FLEE_DISTANCE, WANDER_RADIUS,
+            Faction, FactionTable, Reaction};
+
+/// Runs the behaviour trees of monsters that have one. Goes before `MonsterAI`, which takes the turns
+/// of everything it leaves alone
+pub struct BehaviourSystem {}
+
+impl<'a> System<'a> for BehaviourSystem {
+    type SystemData = ( WriteExpect<'a, Map>,
+                        ReadExpect<'a, Point>,
+                        ReadExpect<'a, Entity>,
+                        Entities<'a>,
+                        ReadExpect<'a, BehaviourLibrary>,
+                        ReadStorage<'a, Behaviour>,
+                        WriteStorage<'a, Viewshed>,
+                        WriteStorage<'a, Monster>,
+                        WriteStorage<'a, Position>,
+                        WriteStorage<'a, WantsToMelee>,
+                        WriteExpect<'a, DijkstraCache>,
+                        WriteExpect<'a, AStarPathfinder>,
+                        WriteExpect<'a, GameRng>,
+                        ReadStorage<'a, MovementProfile>,
+                        WriteStorage<'a, CombatStats>,
+                        ReadStorage<'a, BlocksTile>,
+                        WriteStorage<'a, Door>,
+                        WriteStorage<'a, Renderable>,
+                        WriteStorage<'a, Energy>,
+                        WriteStorage<'a, MyTurn>,
+                        ReadStorage<'a, InBackpack>,
+                        ReadStorage<'a, ProvidesHealing>,
+                        ReadStorage<'a, Name>,
+                        WriteExpect<'a, EventLog>,
+                        ReadStorage<'a, Faction>,
+                        ReadExpect<'a, FactionTable>);
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (map, player_pos, player, entities, library, behaviours, viewsheds, monsters, positions, wants_to_melee, mut dijkstra, pathfinder,
+             rng, profiles, combat_stats, blockers, doors, renderables, mut energies, mut turns, backpacks, healing, names, log, factions, table) = data;
+
+        // only monsters picked by `next_turn` whose tree was loaded, the rest are left to MonsterAI
+        let acting : Vec<(Entity, &BehaviourTree)> = (&entities, &behaviours, &monsters, &positions, &turns).join()
+            .filter_map(|(entity, behaviour, _, _, _)| library.get(&behaviour.tree).map(|tree| (entity, tree)))
+            .collect();
+        if acting.is_empty() { return; }
+        // things have moved since the last monsters acted
+        dijkstra.clear();
+
+        let mut turn = BehaviourTurn {
+            entity: *player, spent: None, map, player_pos: *player_pos, entities, viewsheds, monsters, positions,
+            wants_to_melee, dijkstra, pathfinder, rng, profiles, combat_stats, blockers, doors, renderables, backpacks, healing, names, log,
+            factions, table,
+        };
+        for (entity, tree) in acting {
+            turns.remove(entity);
+            if turn.start(entity) {
+                tree.root.tick(&mut turn);
+            }
+            let cost = turn.spent.take().unwrap_or(ActionCost::Rest);
+            if let Some(energy) = energies.get_mut(entity) {
+                energy.spend(cost);
+            }
+        }
+    }
+}
+
+
+/// A monster's turn, as its behaviour tree sees it
+struct BehaviourTurn<'a> {
+    entity: Entity,
+    /// what the turn went on, once something has used it up
+    spent: Option<ActionCost>,
+    map: WriteExpect<'a, Map>,
+    player_pos: Point,
+    entities: Entities<'a>,
+    viewsheds: WriteStorage<'a, Viewshed>,
+    monsters: WriteStorage<'a, Monster>,
+    positions: WriteStorage<'a, Position>,
+    wants_to_melee: WriteStorage<'a, WantsToMelee>,
+    dijkstra: WriteExpect<'a, DijkstraCache>,
+    pathfinder: WriteExpect<'a, AStarPathfinder>,
+    rng: WriteExpect<'a, GameRng>,
+    profiles: ReadStorage<'a, MovementProfile>,
+    combat_stats: WriteStorage<'a, CombatStats>,
+    blockers: ReadStorage<'a, BlocksTile>,
+    doors: WriteStorage<'a, Door>,
+    renderables: WriteStorage<'a, Renderable>,
+    backpacks: ReadStorage<'a, InBackpack>,
+    healing: ReadStorage<'a, ProvidesHealing>,
+    names: ReadStorage<'a, Name>,
+    log: WriteExpect<'a, EventLog>,
+    factions: ReadStorage<'a, Faction>,
+    table: ReadExpect<'a, FactionTable>,
+}
+
+impl BehaviourTurn<'_> {
+    ///hands the turn to `entity` and brings what it knows about its foes up to date. a monster keeps chasing
+    ///while it knows where the closest one it saw is, and drops back to idle once it gets there and finds nothing.
+    ///false if it's asleep, it only rests until a noise wakes it
+    fn start(&mut self, entity: Entity) -> bool {
+        self.entity = entity;
+        self.spent = None;
+        if self.monster().state == AiState::Asleep { return false; }
+        let here = self.here();
+        let foe = self.visible_foe();
+        let monster = self.monsters.get_mut(entity).expect("acting without a Monster");
+        monster.home.get_or_insert(here);
+        if let Some(foe) = foe {
+            monster.known_player_location = foe;
+            monster.state = AiState::Chase;
+        } else if monster.state == AiState::Chase && here == monster.known_player_location {
+            monster.state = AiState::Idle;
+        }
+        true
+    }
+
+    fn here(&self) -> (i32, i32) {
+        let pos = self.positions.get(self.entity).expect("acting without a Position");
+        (pos.x, pos.y)
+    }
+
+    fn monster(&self) -> &Monster {
+        self.monsters.get(self.entity).expect("acting without a Monster")
+    }
+
+    ///where it thinks the foe it's after is, if it has any idea
+    fn foe_lead(&self) -> Option<(i32, i32)> {
+        let monster = self.monster();
+        (monster.state == AiState::Chase).then_some(monster.known_player_location)
+    }
+
+    ///something it carries that would heal it
+    fn healing_item(&self) -> Option<(Entity, i32)> {
+        (&self.entities, &self.backpacks, &self.healing).join()
+            .find(|(_, pack, _)| pack.owner == self.entity)
+            .map(|(item, _, healing)| (item, healing.heal_amount))
+    }
+
+    ///the first step on the way to (x, y), if there is a way
+    fn step_toward(&mut self, (x, y): (i32, i32)) -> Option<usize> {
+        let (here_x, here_y) = self.here();
+        let walker = self.map.walker_for(self.profiles.get(self.entity));
+        let path = self.pathfinder.search(self.map.xy_idx(here_x, here_y), self.map.xy_idx(x, y), &walker);
+        if path.success { path.steps.get(1).copied() } else { None }
+    }
+
+    ///steps onto `step`, or opens the door standing there, and spends the turn on it. false if the player's in the way
+    fn step(&mut self, step: usize) -> bool {
+        let blocks = self.blockers.contains(self.entity);
+        let pos = self.positions.get_mut(self.entity).expect("acting without a Position");
+        self.spent = take_step(step, pos, blocks, self.player_pos, &mut self.map, &mut self.doors, &mut self.renderables);
+        if self.spent.is_none() { return false; }
+        if let Some(viewshed) = self.viewsheds.get_mut(self.entity) {
+            viewshed.dirty = true;
+        }
+        true
+    }
+
+    ///logs what a monster did, as long as the player could see it happen
+    fn message(&mut self, message: String) {
+        let (x, y) = self.here();
+        if self.map.visible_tiles[self.map.xy_idx(x, y)] {
+            self.log.message(message);
+        }
+    }
+
+    ///what it makes of the creatures around it, the same way `MonsterAI` does
+    fn foes(&self) -> Foes {
+        let creatures = (&self.entities, &self.positions, &self.combat_stats).join().map(|(entity, pos, _)| (entity, pos.x, pos.y));
+        find_foes(self.entity, self.here(), self.viewsheds.get(self.entity), creatures, &self.factions, &self.table)
+    }
+
+    ///where the closest thing it can see that it's hostile to stands
+    fn visible_foe(&self) -> Option<(i32, i32)> {
+        self.foes().visible.map(|(_, x, y)| (x, y))
+    }
+
+    fn name(&self) -> String {
+        self.names.get(self.entity).map_or_else(|| "something".to_string(), |name| name.name.clone())
+    }
+}
+
+impl Agent for BehaviourTurn<'_> {
+    fn check(&mut self, condition: Condition) -> bool {
+        let (x, y) = self.here();
+        match condition {
+            Condition::SeesFoe => self.visible_foe().is_some(),
+            Condition::KnowsFoe => self.foe_lead().is_some(),
+            Condition::NextToFoe => self.foes().adjacent.is_some(),
+            Condition::Hurt(percent) => self.combat_stats.get(self.entity).is_some_and(|stats| stats.hp * 100 <= stats.max_hp * percent),
+            Condition::HasItem => self.healing_item().is_some(),
+            Condition::AtHome => self.monster().home == Some((x, y)),
+        }
+    }
+
+    fn act(&mut self, action: Action) -> bool {
+        // one thing a turn
+        if self.spent.is_some() { return false; }
+        let here = self.here();
+        match action {
+            Action::MoveToward(Target::Foe) => {
+                let Some((x, y)) = self.foe_lead().filter(|lead| *lead != here) else { return false; };
+                let here_idx = self.map.xy_idx(here.0, here.1);
+                let foe_idx = self.map.xy_idx(x, y);
+                let profile = self.profiles.get(self.entity);
+                let walker = self.map.walker_for(profile);
+                // a foe that blocks its tile can't be pathed onto, so it goes by the field flowing out from it like MonsterAI
+                let step = field_from(&mut self.dijkstra, foe_idx, &self.map, profile).find_lowest_exit(here_idx, &walker);
+                // walking into it is what `attack` is for, and `step` won't
+                step.is_some_and(|step| self.step(step))
+            }
+            Action::MoveToward(Target::Home) => {
+                let Some(home) = self.monster().home.filter(|home| *home != here) else { return false; };
+                self.step_toward(home).is_some_and(|step| self.step(step))
+            }
+            Action::Attack => {
+                let Some(target) = self.foes().adjacent else { return false; };
+                self.wants_to_melee.insert(self.entity, WantsToMelee{ target }).expect("Unable to insert attack");
+                self.spent = Some(ActionCost::Attack);
+                true
+            }
+            Action::Flee => {
+                let Some((x, y)) = self.foe_lead() else { return false; };
+                let here_idx = self.map.xy_idx(here.0, here.1);
+                let from = self.map.xy_idx(x, y);
+                let profile = self.profiles.get(self.entity);
+                let walker = self.map.walker_for(profile);
+                let field = field_from(&mut self.dijkstra, from, &self.map, profile);
+                // its own tile is blocked by itself, so how far off it is goes by the closest tile next to it
+                let closest = field.find_lowest_exit(here_idx, &walker);
+                let furthest = field.find_highest_exit(here_idx, &walker);
+                match (closest, furthest) {
+                    (Some(closest), Some(furthest)) if field.map[closest] < FLEE_DISTANCE && field.map[furthest] > field.map[closest] => self.step(furthest),
+                    _ => false,
+                }
+            }
+            Action::UseItem => {
+                let Some((item, heal_amount)) = self.healing_item() else { return false; };
+                let Some(stats) = self.combat_stats.get_mut(self.entity).filter(|stats| stats.hp < stats.max_hp) else { return false; };
+                stats.hp = (stats.hp + heal_amount).min(stats.max_hp);
+                self.entities.delete(item).expect("Unable to delete");
+                let item_name = self.names.get(item).map_or_else(|| "something".to_string(), |name| name.name.clone());
+                let message = format!("{} uses the {}.", self.name(), item_name);
+                self.message(message);
+                self.spent = Some(ActionCost::UseItem);
+                true
+            }
+            Action::CallForHelp(radius) => {
+                let Some(lead) = self.foe_lead() else { return false; };
+                let mut answered = 0;
+                let caller = self.factions.get(self.entity);
+                for (entity, monster, pos) in (&self.entities, &mut self.monsters, &self.positions).join() {
+                    if entity == self.entity || monster.state == AiState::Chase { continue; }
+                    // only its own side comes
+                    if self.table.between(self.factions.get(entity), caller) != Reaction::Friendly { continue; }
+                    if (pos.x - here.0).abs() > radius || (pos.y - here.1).abs() > radius { continue; }
+                    monster.known_player_location = lead;
+                    monster.state = AiState::Chase;
+                    answered += 1;
+                }
+                if answered == 0 { return false; }
+                let message = format!("{} calls for help!", self.name());
+                self.message(message);
+                self.spent = Some(ActionCost::Shout);
+                true
+            }
+            Action::Wander => {
+                let Some(home) = self.monster().home else { return false; };
+                let here_idx = self.map.xy_idx(here.0, here.1);
+                let walker = self.map.walker_for(self.profiles.get(self.entity));
+                let width = self.map.width;
+                let exits : Vec<usize> = walker.get_available_exits(here_idx).into_iter()
+                    .map(|(exit, _)| exit)
+                    .filter(|exit| (*exit as i32 % width - home.0).abs() <= WANDER_RADIUS && (*exit as i32 / width - home.1).abs() <= WANDER_RADIUS)
+                    .collect();
+                if exits.is_empty() { return false; }
+                let step = exits[self.rng.gen_range(0..exits.len())];
+                self.step(step)
+            }
+            Action::Rest => {
+                self.spent = Some(ActionCost::Rest);
+                true
+            }
+        }
+    }
+
+    fn chance(&mut self, percent: i32) -> bool {
+        self.rng.gen_range(0..100) < percent
+    }
+}
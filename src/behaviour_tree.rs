@@ -0,0 +1,290 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+
+/// Where the game looks for behaviour tree files at startup.
+pub const BEHAVIOUR_DIR : &str = "behaviours";
+
+
+#[derive(Debug)]
+pub enum BehaviourError {
+    Io(std::io::Error),
+    Parse { name: String, line: usize, message: String },
+}
+
+impl fmt::Display for BehaviourError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BehaviourError::Io(e) => write!(f, "could not read behaviour tree: {}", e),
+            BehaviourError::Parse { name, line, message } => write!(f, "{} line {}: {}", name, line, message),
+        }
+    }
+}
+
+impl std::error::Error for BehaviourError {}
+
+impl From<std::io::Error> for BehaviourError {
+    fn from(e: std::io::Error) -> Self { BehaviourError::Io(e) }
+}
+
+
+/// How running a node went. Trees are run from the top every turn, so nothing is ever left half done
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Status {
+    Success,
+    Failure,
+}
+
+/// Something a monster can check about itself and its foes, without using up its turn. A foe is anything the
+/// `FactionTable` says it's hostile to
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Condition {
+    /// `sees_foe`
+    SeesFoe,
+    /// `knows_foe`: saw a foe, or was told where one is, and hasn't lost the trail yet
+    KnowsFoe,
+    /// `next_to_foe`
+    NextToFoe,
+    /// `hurt <percent>`: down to that much of its health or less
+    Hurt(i32),
+    /// `has_item`: carries something it could use
+    HasItem,
+    /// `at_home`
+    AtHome,
+}
+
+/// Where `move_toward` heads
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Target {
+    /// wherever the foe it's after is thought to be
+    Foe,
+    Home,
+}
+
+/// Something a monster can do with its turn. Only one action gets done a turn, any after that fail
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Action {
+    /// `move_toward foe` or `move_toward home`: one step along the way, fails if there's no way or it's already there
+    MoveToward(Target),
+    /// `attack`: hits a foe next to it, the player first, fails if there's none
+    Attack,
+    /// `flee`: one step further away from the foe it knows of, fails once it's far enough away or there's nowhere further to go
+    Flee,
+    /// `use_item`: uses something it carries, fails if there's nothing that would help
+    UseItem,
+    /// `call_for_help <radius>`: tells the monsters on its side within that many tiles where its foe is, fails if
+    /// none of them didn't know already
+    CallForHelp(i32),
+    /// `wander`: a step somewhere near home, fails if it's strayed too far for that
+    Wander,
+    /// `rest`: does nothing, always works
+    Rest,
+}
+
+/// One node of a behaviour tree
+#[derive(PartialEq, Eq, Clone, Debug)]
+pub enum BehaviourNode {
+    /// runs its children in order until one fails, succeeds if none did
+    Sequence(Vec<BehaviourNode>),
+    /// runs its children in order until one succeeds, fails if none did
+    Selector(Vec<BehaviourNode>),
+    Condition(Condition),
+    Action(Action),
+    /// `invert`: turns its child's success into failure and the other way around
+    Invert(Box<BehaviourNode>),
+    /// `succeed`: runs its child and succeeds whatever happened
+    Succeed(Box<BehaviourNode>),
+    /// `chance <percent>`: only runs its child that often, failing the rest of the time
+    Chance(i32, Box<BehaviourNode>),
+}
+
+/// What a tree runs against, the monster whose turn it is
+pub trait Agent {
+    ///true if `condition` holds right now
+    fn check(&mut self, condition: Condition) -> bool;
+    ///tries to carry out `action`, true if it did
+    fn act(&mut self, action: Action) -> bool;
+    ///true `percent` times in a hundred
+    fn chance(&mut self, percent: i32) -> bool;
+}
+
+impl BehaviourNode {
+    pub fn tick(&self, agent: &mut dyn Agent) -> Status {
+        let succeeded = match self {
+            BehaviourNode::Sequence(children) => children.iter().all(|child| child.tick(agent) == Status::Success),
+            BehaviourNode::Selector(children) => children.iter().any(|child| child.tick(agent) == Status::Success),
+            BehaviourNode::Condition(condition) => agent.check(*condition),
+            BehaviourNode::Action(action) => agent.act(*action),
+            BehaviourNode::Invert(child) => child.tick(agent) == Status::Failure,
+            BehaviourNode::Succeed(child) => { child.tick(agent); true }
+            BehaviourNode::Chance(percent, child) => agent.chance(*percent) && child.tick(agent) == Status::Success,
+        };
+        if succeeded { Status::Success } else { Status::Failure }
+    }
+}
+
+
+/// Decides what a monster does on its turn. Written as a text file: an optional `name: <name>` line
+/// (the file's name otherwise), a `---` line, then one node per line with its children indented under it.
+/// Lines starting with `#` are comments.
+///
+/// ```text
+/// sequence  selector             composites, at least one child
+/// invert  succeed  chance <n>    decorators, exactly one child
+/// sees_foe  knows_foe  next_to_foe  hurt <percent>  has_item  at_home
+/// move_toward foe|home  attack  flee  use_item  call_for_help <radius>  wander  rest
+/// ```
+#[derive(Clone, Debug)]
+pub struct BehaviourTree {
+    pub name: String,
+    pub root: BehaviourNode,
+}
+
+/// A node's line once comments and blank lines are gone: line number, indentation and the words on it
+type NodeLine<'a> = (usize, usize, &'a str);
+
+impl BehaviourTree {
+    ///reads a tree from its text, `name` is what it's called unless the text says otherwise, and is used in error messages
+    pub fn parse(name: &str, text: &str) -> Result<BehaviourTree, BehaviourError> {
+        let error = |line: usize, message: String| BehaviourError::Parse { name: name.to_string(), line: line + 1, message };
+
+        let mut tree_name = name.to_string();
+        let mut lines = text.lines().enumerate();
+        for (n, line) in lines.by_ref() {
+            let line = line.trim();
+            if line == "---" { break; }
+            if line.is_empty() || line.starts_with('#') { continue; }
+            let (key, value) = line.split_once(':').ok_or_else(|| error(n, format!("expected `key: value` or `---`, got `{}`", line)))?;
+            match key.trim() {
+                "name" => tree_name = value.trim().to_string(),
+                other => return Err(error(n, format!("unknown key {}", other))),
+            }
+        }
+
+        let nodes : Vec<NodeLine> = lines
+            .filter(|(_, line)| !line.trim().is_empty() && !line.trim().starts_with('#'))
+            .map(|(n, line)| (n, line.len() - line.trim_start().len(), line.trim()))
+            .collect();
+        if nodes.is_empty() {
+            return Err(error(0, "no tree after `---`".to_string()));
+        }
+        let mut next = 0;
+        let root = parse_node(&nodes, &mut next).map_err(|(n, message)| error(n, message))?;
+        if let Some((n, _, _)) = nodes.get(next) {
+            return Err(error(*n, "a tree has one node at the top, put these under a selector or sequence".to_string()));
+        }
+        Ok(BehaviourTree { name: tree_name, root })
+    }
+}
+
+///reads the node on `lines[*next]` along with everything indented under it, leaving `next` on the line after
+fn parse_node(lines: &[NodeLine], next: &mut usize) -> Result<BehaviourNode, (usize, String)> {
+    let (n, indent, text) = lines[*next];
+    *next += 1;
+
+    let mut children = Vec::new();
+    if let Some(&(_, child_indent, _)) = lines.get(*next).filter(|(_, i, _)| *i > indent) {
+        while let Some(&(m, i, _)) = lines.get(*next) {
+            if i <= indent { break; }
+            if i != child_indent {
+                return Err((m, "indented differently from the nodes before it".to_string()));
+            }
+            children.push(parse_node(lines, next)?);
+        }
+    }
+
+    let mut words = text.split_whitespace();
+    let keyword = words.next().unwrap_or_default();
+    let args : Vec<&str> = words.collect();
+    let error = |message: String| Err((n, message));
+    let no_args = || if args.is_empty() { Ok(()) } else { Err((n, format!("{} doesn't take anything after it", keyword))) };
+    let number = || match args[..] {
+        [arg] => arg.parse::<i32>().map_err(|_| (n, format!("{} needs a whole number", keyword))),
+        _ => Err((n, format!("{} needs a whole number", keyword))),
+    };
+
+    let composite = matches!(keyword, "sequence" | "selector");
+    let decorator = matches!(keyword, "invert" | "succeed" | "chance");
+    if composite && children.is_empty() {
+        return error(format!("{} needs at least one node under it", keyword));
+    }
+    if decorator && children.len() != 1 {
+        return error(format!("{} needs exactly one node under it", keyword));
+    }
+    if !composite && !decorator && !children.is_empty() {
+        return error(format!("{} can't have nodes under it", keyword));
+    }
+
+    let node = match keyword {
+        "sequence" => { no_args()?; BehaviourNode::Sequence(children) }
+        "selector" => { no_args()?; BehaviourNode::Selector(children) }
+        "invert" => { no_args()?; BehaviourNode::Invert(Box::new(children.remove(0))) }
+        "succeed" => { no_args()?; BehaviourNode::Succeed(Box::new(children.remove(0))) }
+        "chance" => BehaviourNode::Chance(number()?, Box::new(children.remove(0))),
+
+        "sees_foe" => { no_args()?; BehaviourNode::Condition(Condition::SeesFoe) }
+        "knows_foe" => { no_args()?; BehaviourNode::Condition(Condition::KnowsFoe) }
+        "next_to_foe" => { no_args()?; BehaviourNode::Condition(Condition::NextToFoe) }
+        "hurt" => BehaviourNode::Condition(Condition::Hurt(number()?)),
+        "has_item" => { no_args()?; BehaviourNode::Condition(Condition::HasItem) }
+        "at_home" => { no_args()?; BehaviourNode::Condition(Condition::AtHome) }
+
+        "move_toward" => match args[..] {
+            ["foe"] => BehaviourNode::Action(Action::MoveToward(Target::Foe)),
+            ["home"] => BehaviourNode::Action(Action::MoveToward(Target::Home)),
+            _ => return error("move_toward needs foe or home".to_string()),
+        },
+        "attack" => { no_args()?; BehaviourNode::Action(Action::Attack) }
+        "flee" => { no_args()?; BehaviourNode::Action(Action::Flee) }
+        "use_item" => { no_args()?; BehaviourNode::Action(Action::UseItem) }
+        "call_for_help" => BehaviourNode::Action(Action::CallForHelp(number()?)),
+        "wander" => { no_args()?; BehaviourNode::Action(Action::Wander) }
+        "rest" => { no_args()?; BehaviourNode::Action(Action::Rest) }
+        other => return error(format!("unknown node {}", other)),
+    };
+    Ok(node)
+}
+
+
+/// Resource holding every behaviour tree monsters can be given, by name.
+#[derive(Clone, Debug, Default)]
+pub struct BehaviourLibrary {
+    pub trees: HashMap<String, BehaviourTree>,
+}
+
+impl BehaviourLibrary {
+    pub fn new() -> BehaviourLibrary {
+        BehaviourLibrary { trees: HashMap::new() }
+    }
+
+    ///loads every `.txt` file in `dir`. two trees with the same name is an error, one would quietly go missing otherwise
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<BehaviourLibrary, BehaviourError> {
+        let mut paths : Vec<_> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+            .collect();
+        paths.sort();
+
+        let mut library = BehaviourLibrary::new();
+        for path in paths {
+            let text = fs::read_to_string(&path)?;
+            let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+            let tree = BehaviourTree::parse(&stem, &text).map_err(|e| match e {
+                BehaviourError::Parse { line, message, .. } => BehaviourError::Parse { name: path.display().to_string(), line, message },
+                e => e,
+            })?;
+            if library.trees.contains_key(&tree.name) {
+                return Err(BehaviourError::Parse { name: path.display().to_string(), line: 1,
+                    message: format!("there's already a tree called {}", tree.name) });
+            }
+            library.trees.insert(tree.name.clone(), tree);
+        }
+        Ok(library)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BehaviourTree> {
+        self.trees.get(name)
+    }
+}
@@ -0,0 +1,215 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use specs::prelude::*;
+use specs::saveload::{SimpleMarker, SimpleMarkerAllocator};
+
+use super::*;
+
+
+/// Sets up a `State` with everything the engine needs: registered components, the map,
+/// the event log, the player and its position. Games can register their own components and
+/// systems on top before calling `build`.
+pub struct GameBuilder {
+    ecs: World,
+    systems: Vec<Box<dyn for<'a> RunNow<'a>>>,
+    map: Option<Map>,
+    seed: Option<u64>,
+    spawn_monsters: bool,
+    keep_levels: bool,
+    style: Option<MapStyle>,
+    map_size: (i32, i32),
+}
+
+impl Default for GameBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl GameBuilder {
+    ///creates a builder with all of the engine's components registered
+    pub fn new() -> GameBuilder {
+        let mut ecs = World::new();
+        ecs.register::<Position>();
+        ecs.register::<OtherLevelPosition>();
+        ecs.register::<Renderable>();
+        ecs.register::<Player>();
+        ecs.register::<Viewshed>();
+        ecs.register::<Monster>();
+        ecs.register::<Behaviour>();
+        ecs.register::<Hearing>();
+        ecs.register::<Faction>();
+        ecs.register::<Dialogue>();
+        ecs.register::<Name>();
+        ecs.register::<BlocksTile>();
+        ecs.register::<CombatStats>();
+        ecs.register::<MovementProfile>();
+        ecs.register::<Door>();
+        ecs.register::<Item>();
+        ecs.register::<Key>();
+        ecs.register::<ProvidesHealing>();
+        ecs.register::<InBackpack>();
+        ecs.register::<LightSource>();
+        ecs.register::<Energy>();
+        ecs.register::<MyTurn>();
+        ecs.register::<WantsToMelee>();
+        ecs.register::<SufferDamage>();
+        ecs.register::<SimpleMarker<SerializeMe>>();
+        ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+        ecs.insert(DijkstraCache::new());
+        ecs.insert(FovCache::new());
+        ecs.insert(Noises::new());
+        ecs.insert(AStarPathfinder::default());
+        ecs.insert(Camera::new(DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT));
+        ecs.insert(PrefabLibrary::new());
+        ecs.insert(BehaviourLibrary::new());
+        ecs.insert(FactionTable::standard());
+
+        GameBuilder {
+            ecs,
+            systems: Vec::new(),
+            map: None,
+            seed: None,
+            spawn_monsters: true,
+            keep_levels: true,
+            style: None,
+            map_size: (DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT),
+        }
+    }
+
+    ///registers a game-specific component
+    pub fn register<C>(mut self) -> Self
+    where
+        C: Component,
+        C::Storage: Default,
+    {
+        self.ecs.register::<C>();
+        self
+    }
+
+    ///adds a game-specific system, run every turn after the engine's systems in the order added
+    pub fn with_system<S>(mut self, system: S) -> Self
+    where
+        S: for<'a> RunNow<'a> + 'static,
+    {
+        self.systems.push(Box::new(system));
+        self
+    }
+
+    ///uses the given map instead of generating one; the player starts in the centre of its first room
+    ///and monsters in the centres of the others
+    pub fn with_map(mut self, map: Map) -> Self {
+        self.map = Some(map);
+        self
+    }
+
+    ///lays out every generated level in `style`, by default each level below the first picks one at random
+    pub fn with_map_style(mut self, style: MapStyle) -> Self {
+        self.style = Some(style);
+        self
+    }
+
+    ///generates levels `width` tiles wide and `height` high instead of the default 80 by 50
+    pub fn with_map_size(mut self, width: i32, height: i32) -> Self {
+        self.map_size = (width, height);
+        self
+    }
+
+    ///stamps prefabs from `library` into generated levels, none are used otherwise
+    pub fn with_prefabs(mut self, library: PrefabLibrary) -> Self {
+        self.ecs.insert(library);
+        self
+    }
+
+    ///lets monsters with a `Behaviour` run the trees in `library`, they fall back on `MonsterAI`'s states otherwise
+    pub fn with_behaviours(mut self, library: BehaviourLibrary) -> Self {
+        self.ecs.insert(library);
+        self
+    }
+
+    ///decides how factions take to each other with `table` instead of `FactionTable::standard`
+    pub fn with_factions(mut self, table: FactionTable) -> Self {
+        self.ecs.insert(table);
+        self
+    }
+
+    ///seeds the game's rng so the same seed always produces the same dungeon and outcomes
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    ///don't put the engine's default monsters in the rooms, or the player's companion next to it
+    pub fn without_monsters(mut self) -> Self {
+        self.spawn_monsters = false;
+        self
+    }
+
+    ///generate a fresh level every time the player takes the stairs, instead of keeping the ones already visited
+    pub fn forget_visited_levels(mut self) -> Self {
+        self.keep_levels = false;
+        self
+    }
+
+    ///access to the world before it's built, e.g. to insert resources
+    pub fn world_mut(&mut self) -> &mut World {
+        &mut self.ecs
+    }
+
+    ///restores a saved game instead of starting a new one; the map, seed, monster and level options are ignored.
+    ///a loaded game can't be reproduced from its seed so its input isn't recorded
+    pub fn load(self, path: impl AsRef<Path>) -> Result<State, SaveError> {
+        let mut ecs = self.ecs;
+        load_game(&mut ecs, path)?;
+
+        Ok(State {
+            ecs,
+            systems: self.systems,
+        })
+    }
+
+    ///inserts the rng, map, log, player and monsters and hands back a ready to run `State`
+    pub fn build(self) -> State {
+        let mut ecs = self.ecs;
+        ecs.insert(RunState::PreRun);
+
+        let rng = match self.seed {
+            Some(seed) => GameRng::seeded(seed),
+            None => GameRng::from_entropy(),
+        };
+        ecs.insert(Recording { map_style: self.style, map_size: Some(self.map_size), ..Recording::new(rng.seed()) });
+        ecs.insert(rng);
+        let (width, height) = self.map_size;
+        ecs.insert(Dungeon { levels: HashMap::new(), keep_levels: self.keep_levels, style: self.style, width, height });
+
+        let mut level = match self.map {
+            Some(map) => BuiltLevel::from_rooms(map),
+            None => build_level(&mut ecs, 1),
+        };
+        spawner::spawn_level_doors(&mut ecs, &mut level);
+        spawner::spawn_level_torches(&mut ecs, &level);
+        let (player_x, player_y) = (level.start.x, level.start.y);
+        let map = level.map;
+
+        if self.spawn_monsters {
+            spawner::spawn_level_monsters(&mut ecs, &level.spawn_points, map.depth);
+        }
+
+        ecs.insert(Point::new(player_x, player_y));
+        ecs.insert(map);
+        ecs.insert(EventLog::new());
+
+        let player_entity = spawner::player(&mut ecs, player_x, player_y);
+        ecs.insert(player_entity);
+        if self.spawn_monsters {
+            let squire = spawner::squire(&mut ecs, player_x, player_y);
+            gather_followers(&mut ecs, &[squire]);
+        }
+
+        State {
+            ecs,
+            systems: self.systems,
+        }
+    }
+}
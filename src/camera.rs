@@ -0,0 +1,65 @@
+use super::{Map, Point, TILE_SIZE, SCALE};
+
+
+/// Resource describing which part of the map is on screen. `x` and `y` are the map tile shown
+/// in the top left corner, `width` and `height` how many tiles fit in the window.
+/// Follows the player while it's far enough from the edges, a map smaller than the window is centred.
+pub struct Camera {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Camera {
+    ///a camera showing `width` by `height` tiles, starting at the top left of the map
+    pub fn new(width: i32, height: i32) -> Camera {
+        Camera { x: 0, y: 0, width, height }
+    }
+
+    ///size of one tile on screen, in pixels
+    pub fn tile_pixels() -> f32 {
+        TILE_SIZE as f32 * SCALE
+    }
+
+    ///changes how many tiles fit on screen to match a window `width` by `height` pixels
+    pub fn fit_screen(&mut self, width: i32, height: i32) {
+        let tile = Self::tile_pixels();
+        self.width = ((width as f32 / tile).ceil() as i32).max(1);
+        self.height = ((height as f32 / tile).ceil() as i32).max(1);
+    }
+
+    ///centres on `target`, without scrolling past the edges of `map`
+    pub fn follow(&mut self, target: Point, map: &Map) {
+        self.x = Self::axis(target.x, self.width, map.width);
+        self.y = Self::axis(target.y, self.height, map.height);
+    }
+
+    fn axis(target: i32, view: i32, map: i32) -> i32 {
+        if map <= view {
+            // the whole map fits, put it in the middle
+            -(view - map) / 2
+        } else {
+            (target - view / 2).clamp(0, map - view)
+        }
+    }
+
+    ///true if map tile (x, y) is on screen
+    pub fn contains(&self, x: i32, y: i32) -> bool {
+        x >= self.x && x < self.x + self.width && y >= self.y && y < self.y + self.height
+    }
+
+    ///top left pixel of map tile (x, y) on screen
+    pub fn map_to_screen(&self, x: i32, y: i32) -> (f32, f32) {
+        let tile = Self::tile_pixels();
+        ((x - self.x) as f32 * tile, (y - self.y) as f32 * tile)
+    }
+
+    ///the map tile under screen pixel (x, y), None if that pixel isn't over the map
+    pub fn screen_to_map(&self, x: f32, y: f32, map: &Map) -> Option<Point> {
+        let tile = Self::tile_pixels();
+        let map_x = (x / tile).floor() as i32 + self.x;
+        let map_y = (y / tile).floor() as i32 + self.y;
+        map.in_bounds(map_x, map_y).then(|| Point::new(map_x, map_y))
+    }
+}
@@ -0,0 +1,39 @@
+#[cfg(feature = "render")]
+pub use raylib::color::Color;
+
+///stand-in for raylib's Color so components still compile without the render feature
+#[cfg(not(feature = "render"))]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Color {
+    pub r: u8,
+    pub g: u8,
+    pub b: u8,
+    pub a: u8,
+}
+
+#[cfg(not(feature = "render"))]
+impl Color {
+    pub const WHITE: Color = Color::new(255, 255, 255, 255);
+    pub const GRAY: Color = Color::new(128, 128, 128, 255);
+    pub const BLACK: Color = Color::new(0, 0, 0, 255);
+    pub const GOLD: Color = Color::new(255, 203, 0, 255);
+
+    pub const fn new(r: u8, g: u8, b: u8, a: u8) -> Color {
+        Color { r, g, b, a }
+    }
+}
+
+///(de)serializes a Color as an `[r, g, b, a]` array, for use with `#[serde(with = "color_serde")]`
+pub mod color_serde {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+    use super::Color;
+
+    pub fn serialize<S: Serializer>(color: &Color, serializer: S) -> Result<S::Ok, S::Error> {
+        [color.r, color.g, color.b, color.a].serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Color, D::Error> {
+        let [r, g, b, a] = <[u8; 4]>::deserialize(deserializer)?;
+        Ok(Color::new(r, g, b, a))
+    }
+}
@@ -1,52 +1,171 @@
-use raylib::color::Color;
+use crate::{Color, color_serde, TileType, MoveCost, DoorState, Map, FovAlgorithm, VisibleTiles, Point, ActionCost, TURN_ENERGY};
+use serde::{Serialize, Deserialize};
 use specs::prelude::*;
+use specs::saveload::{ConvertSaveload, Marker};
+// the saveload derive names its error type NoError, which specs now calls Infallible
+use std::convert::Infallible as NoError;
 use specs_derive::*;
 
 
 
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Renderable {
     pub index: u8,
+    #[serde(with = "color_serde")]
     pub color: Color,
 }
 
-#[derive(Component)]
+/// Gives off light, lighting up every tile it can see within `range`. Brightest next to the source,
+/// fading out towards the edge of its range
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct LightSource {
+    #[serde(with = "color_serde")]
+    pub color: Color,
+    pub range: i32,
+    /// how bright it is right next to it, 1.0 lights a tile fully
+    pub intensity: f32,
+}
+
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Position {
     pub x: i32,
     pub y: i32,
 }
 
+/// Where an entity is while the player is on another level; swapped back to a `Position` on return
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct OtherLevelPosition {
+    pub x: i32,
+    pub y: i32,
+    pub depth: i32,
+}
 
-#[derive(Component, Debug)]
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Player {
     pub health : i32,
 }
 
 
-#[derive(Component)]
+#[derive(Component, Serialize, Deserialize, Clone)]
 pub struct Viewshed {
-    pub visible_tiles : Vec<(i32,i32)>,
+    /// worked out again after loading, so not saved
+    #[serde(skip)]
+    pub visible_tiles : VisibleTiles,
     pub range : i32,
-    pub dirty : bool
+    /// forces the view to be redone even if nothing seems to have changed
+    pub dirty : bool,
+    #[serde(default)]
+    pub algorithm : FovAlgorithm,
+    /// where it was last worked out from and the map revision at the time, it's left alone while both still match
+    #[serde(skip)]
+    pub seen_from : Option<(Point, u64)>,
 }
 
-#[derive(Component)]
-pub struct Monster {
-    pub seen_player : bool,
-    pub known_player_location: (i32, i32)
+/// Lets an entity take turns. Time passes in ticks, each adding `speed` to `energy`, and once it holds
+/// `TURN_ENERGY` it can act, paying for whatever it does. `NORMAL_SPEED` is an ordinary pace, twice
+/// that acts twice as often. Anything without one never gets a turn
+#[derive(Component, Serialize, Deserialize, Clone, Debug)]
+pub struct Energy {
+    pub speed : i32,
+    pub energy : i32,
+}
+
+impl Energy {
+    pub fn new(speed: i32) -> Energy {
+        Energy { speed, energy: 0 }
+    }
+
+    ///true if it has enough energy to act
+    pub fn ready(&self) -> bool {
+        self.energy >= TURN_ENERGY
+    }
+
+    pub fn spend(&mut self, cost: ActionCost) {
+        self.energy -= cost.energy();
+    }
+
+    ///how many ticks until it's ready, 0 if it already is
+    pub fn ticks_until_ready(&self) -> i32 {
+        let speed = self.speed.max(1);
+        ((TURN_ENERGY - self.energy).max(0) + speed - 1) / speed
+    }
 }
 
+/// Given by `next_turn` to whatever acts next, the acting system takes it away again
 #[derive(Component, Debug)]
+pub struct MyTurn {}
+
+/// What a monster is up to, `MonsterAI` moves it from one to the next
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum AiState {
+    /// standing around at home
+    #[default]
+    Idle,
+    /// sees nothing and does nothing until a noise wakes it, see `Hearing`
+    Asleep,
+    /// strolling to a spot not far from home
+    Wander { x: i32, y: i32 },
+    /// going for the player, or whatever else it's hostile to, or for where that was last seen
+    Chase,
+    /// poking around where the player was last seen, giving up once `turns` runs out
+    Search { turns: i32 },
+    /// badly hurt and getting away from the player
+    Flee,
+    /// lost the player and heading back home
+    ReturnHome,
+    /// on the player's side and keeping up with it
+    Follow,
+}
+
+#[derive(Component, Serialize, Deserialize, Clone)]
+pub struct Monster {
+    /// where the player, or whatever else it's after, was last seen, only meaningful while chasing, searching or fleeing
+    pub known_player_location: (i32, i32),
+    #[serde(default)]
+    pub state: AiState,
+    /// where it wanders around and goes back to, taken from wherever it first acts if not set
+    #[serde(default)]
+    pub home: Option<(i32, i32)>,
+}
+
+/// Notices noises, see `HearingSystem`. Anything at least `threshold` loud by the time it gets here is heard
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Hearing {
+    pub threshold : i32
+}
+
+/// Runs the named tree from the `BehaviourLibrary` on its turns instead of `MonsterAI`'s states,
+/// which it falls back on if no tree by that name was loaded
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Behaviour {
+    pub tree : String
+}
+
+/// Which side it's on, the `FactionTable` says how each side takes to the others. Anything without one counts
+/// as `DEFAULT_FACTION`
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Faction {
+    pub name : String
+}
+
+/// What it says when the player bumps into it without meaning harm, one line picked at random each time
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Dialogue {
+    pub lines : Vec<String>
+}
+
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct Name {
     pub name : String
 }
 
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct BlocksTile {}
 
 
-#[derive(Component, Debug)]
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
 pub struct CombatStats {
     pub max_hp : i32,
     pub hp : i32,
@@ -54,6 +173,135 @@ pub struct CombatStats {
     pub power : i32
 }
 
+/// How an entity gets across terrain. Tiles not listed cost what `TileType::move_cost` says.
+/// The name tells profiles apart, so two profiles with the same name must price things the same.
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct MovementProfile {
+    pub name : String,
+    pub costs : Vec<(TileType, MoveCost)>,
+    /// walks up to closed doors and opens them, instead of treating them as walls
+    #[serde(default)]
+    pub opens_doors : bool
+}
+
+impl MovementProfile {
+    ///what stepping onto `tile` costs this mover
+    pub fn cost(&self, tile: TileType) -> MoveCost {
+        self.costs.iter()
+            .find(|(t, _)| *t == tile)
+            .map(|(_, cost)| *cost)
+            .unwrap_or_else(|| tile.move_cost())
+    }
+
+    ///the smallest cost multiplier this mover ever pays, capped at floor cost
+    pub fn cheapest_step(&self) -> f32 {
+        self.costs.iter()
+            .filter_map(|(_, cost)| match cost {
+                MoveCost::Cost(multiplier) => Some(*multiplier),
+                _ => None,
+            })
+            .fold(1.0, f32::min)
+    }
+
+    ///the same profile, but able to open doors
+    pub fn opening_doors(mut self) -> MovementProfile {
+        self.opens_doors = true;
+        self.name.push_str("+doors");
+        self
+    }
+
+    ///on foot, paying what the terrain says
+    pub fn walking() -> MovementProfile {
+        MovementProfile {
+            name: "walking".to_string(),
+            costs: Vec::new(),
+            opens_doors: false
+        }
+    }
+
+    ///flies over everything that isn't a wall
+    pub fn flying() -> MovementProfile {
+        MovementProfile {
+            name: "flying".to_string(),
+            costs: vec![
+                (TileType::Mud, MoveCost::Cost(1.0)),
+                (TileType::Water, MoveCost::Cost(1.0)),
+                (TileType::Rubble, MoveCost::Cost(1.0)),
+                (TileType::Lava, MoveCost::Cost(1.0)),
+                (TileType::Chasm, MoveCost::Cost(1.0)),
+            ],
+            opens_doors: false
+        }
+    }
+
+    ///at home in water, would rather go around mud than through it
+    pub fn amphibious() -> MovementProfile {
+        MovementProfile {
+            name: "amphibious".to_string(),
+            costs: vec![
+                (TileType::Water, MoveCost::Cost(0.5)),
+                (TileType::Mud, MoveCost::Cost(1.0)),
+            ],
+            opens_doors: false
+        }
+    }
+}
+
+/// Sprites for doors, they change when the door opens or closes
+pub const DOOR_CLOSED_SPRITE : u8 = 36;
+pub const DOOR_OPEN_SPRITE : u8 = 37;
+
+/// A door on its tile. Shut it blocks sight and movement, locked it only opens for someone with a `Key`
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Door {
+    pub open : bool,
+    pub locked : bool
+}
+
+impl Door {
+    pub fn state(&self) -> DoorState {
+        match (self.open, self.locked) {
+            (true, _) => DoorState::Open,
+            (false, false) => DoorState::Closed,
+            (false, true) => DoorState::Locked,
+        }
+    }
+
+    ///opens or closes the door standing on `idx`, updating its sprite and the map straight away so sight
+    ///and paths change this turn
+    pub fn set_open(&mut self, open: bool, idx: usize, map: &mut Map, renderable: Option<&mut Renderable>) {
+        self.open = open;
+        if open {
+            self.locked = false;
+        }
+        map.doors[idx] = Some(self.state());
+        map.bump_revision();
+        if let Some(renderable) = renderable {
+            renderable.index = if open { DOOR_OPEN_SPRITE } else { DOOR_CLOSED_SPRITE };
+        }
+    }
+}
+
+/// Can be picked up
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Item {}
+
+/// Unlocks any one locked door, and is used up doing it
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct Key {}
+
+/// Heals whoever uses it by up to `heal_amount`, and is used up doing it
+#[derive(Component, Debug, Serialize, Deserialize, Clone)]
+pub struct ProvidesHealing {
+    pub heal_amount : i32
+}
+
+/// Carried by `owner` instead of lying on the map
+#[derive(Component, Debug, ConvertSaveload, Clone)]
+pub struct InBackpack {
+    pub owner : Entity
+}
+
 #[derive(Component, Debug, Clone)]
 pub struct WantsToMelee {
     pub target : Entity
@@ -75,3 +323,7 @@ impl SufferDamage {
         }
     }
 }
+
+
+/// Marks entities that get written to save files
+pub struct SerializeMe;
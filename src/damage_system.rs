@@ -1,7 +1,7 @@
 use specs::prelude::*;
 use crate::EventLog;
 
-use super::{CombatStats, SufferDamage, Player};
+use super::{CombatStats, SufferDamage, Player, InBackpack};
 
 pub struct DamageSystem {}
 
@@ -12,7 +12,7 @@ impl<'a> System<'a> for DamageSystem {
     fn run(&mut self, data : Self::SystemData) {
         let (mut stats, mut damage) = data;
 
-        for (mut stats, damage) in (&mut stats, &damage).join() {
+        for (stats, damage) in (&mut stats, &damage).join() {
             stats.hp -= damage.amount.iter().sum::<i32>();
         }
 
@@ -34,13 +34,27 @@ pub fn delete_the_dead(ecs : &mut World) {
                 let player = players.get(entity);
                 match player {
                     None => dead.push(entity),
-                    Some(_) => log.message(format!("You are dead"))
+                    Some(_) => log.message("You are dead".to_string())
                 }
             }
         }
     }
 
     for victim in dead {
-        ecs.delete_entity(victim).expect("Unable to delete");
+        delete_with_belongings(ecs, victim);
     }    
 }
+
+///deletes `entity` along with whatever it was carrying
+pub fn delete_with_belongings(ecs : &mut World, entity: Entity) {
+    let carried : Vec<Entity> = {
+        let entities = ecs.entities();
+        let backpacks = ecs.read_storage::<InBackpack>();
+        (&entities, &backpacks).join()
+            .filter(|(_, pack)| pack.owner == entity)
+            .map(|(item, _)| item)
+            .collect()
+    };
+    ecs.delete_entities(&carried).expect("Unable to delete");
+    ecs.delete_entity(entity).expect("Unable to delete");
+}
@@ -0,0 +1,169 @@
+use std::cmp::Ordering;
+use std::collections::{BinaryHeap, HashMap};
+
+use crate::{BaseMap, Algorithm2D, Map, MovementProfile};
+
+
+/// A distance field: for every tile, the cost of the cheapest walk to the nearest source tile.
+/// Tiles that couldn't be reached (or are further than `max_depth`) hold `f32::MAX`.
+/// Walk downhill with `find_lowest_exit` to approach the sources, uphill with `find_highest_exit` to get away.
+#[derive(Clone, Debug)]
+pub struct DijkstraMap {
+    pub map: Vec<f32>,
+    pub size_x: usize,
+    pub size_y: usize,
+    pub max_depth: f32,
+}
+
+///entry in the open list, ordered so the BinaryHeap pops the cheapest first
+#[derive(Copy, Clone)]
+struct Frontier {
+    idx: usize,
+    cost: f32,
+}
+
+impl PartialEq for Frontier {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for Frontier {}
+
+impl Ord for Frontier {
+    fn cmp(&self, b: &Self) -> Ordering {
+        b.cost.total_cmp(&self.cost).then_with(|| b.idx.cmp(&self.idx))
+    }
+}
+
+impl PartialOrd for Frontier {
+    fn partial_cmp(&self, b: &Self) -> Option<Ordering> {
+        Some(self.cmp(b))
+    }
+}
+
+impl DijkstraMap {
+    ///builds a distance field flowing out from every tile in `starts`
+    pub fn new(starts: &[usize], map: &dyn Algorithm2D, max_depth: f32) -> DijkstraMap {
+        let mut result = DijkstraMap::new_empty(map, max_depth);
+        result.build(starts, map);
+        result
+    }
+
+    ///a field with nothing reachable, sized to `map`; fill it with `build` or `build_weighted`
+    pub fn new_empty(map: &dyn Algorithm2D, max_depth: f32) -> DijkstraMap {
+        let size = map.dimensions();
+        let size_x = size.x as usize;
+        let size_y = size.y as usize;
+        DijkstraMap {
+            map: vec![f32::MAX; size_x * size_y],
+            size_x,
+            size_y,
+            max_depth,
+        }
+    }
+
+    ///marks every tile unreachable again
+    pub fn clear(&mut self) {
+        self.map.iter_mut().for_each(|v| *v = f32::MAX);
+    }
+
+    ///recomputes the field with every source at distance 0
+    pub fn build(&mut self, starts: &[usize], map: &dyn BaseMap) {
+        let weighted : Vec<(usize, f32)> = starts.iter().map(|s| (*s, 0.0)).collect();
+        self.build_weighted(&weighted, map);
+    }
+
+    ///recomputes the field with each source starting at its own value, e.g. to make some goals more attractive than others
+    pub fn build_weighted(&mut self, starts: &[(usize, f32)], map: &dyn BaseMap) {
+        self.clear();
+        let mut open_list : BinaryHeap<Frontier> = BinaryHeap::with_capacity(self.map.len());
+
+        for (idx, cost) in starts.iter() {
+            if *idx < self.map.len() && *cost < self.map[*idx] {
+                self.map[*idx] = *cost;
+                open_list.push(Frontier { idx: *idx, cost: *cost });
+            }
+        }
+
+        while let Some(q) = open_list.pop() {
+            // a cheaper route to this tile was already expanded
+            if q.cost > self.map[q.idx] {
+                continue;
+            }
+            let values = &mut self.map;
+            let max_depth = self.max_depth;
+            map.for_each_exit(q.idx, &mut |exit, step_cost| {
+                let cost = q.cost + step_cost;
+                if cost > max_depth || exit >= values.len() {
+                    return;
+                }
+                if cost < values[exit] {
+                    values[exit] = cost;
+                    open_list.push(Frontier { idx: exit, cost });
+                }
+            });
+        }
+    }
+
+    ///true if the tile was reached while building
+    pub fn is_reachable(&self, idx: usize) -> bool {
+        self.map.get(idx).is_some_and(|v| *v < f32::MAX)
+    }
+
+    ///the neighbour of `position` closest to a source, or None if no neighbour was reached
+    pub fn find_lowest_exit(&self, position: usize, map: &dyn BaseMap) -> Option<usize> {
+        map.get_available_exits(position)
+            .into_iter()
+            .filter(|(exit, _)| self.is_reachable(*exit))
+            .min_by(|a, b| self.map[a.0].total_cmp(&self.map[b.0]))
+            .map(|(exit, _)| exit)
+    }
+
+    ///the neighbour of `position` furthest from every source, or None if no neighbour was reached
+    pub fn find_highest_exit(&self, position: usize, map: &dyn BaseMap) -> Option<usize> {
+        map.get_available_exits(position)
+            .into_iter()
+            .filter(|(exit, _)| self.is_reachable(*exit))
+            .max_by(|a, b| self.map[a.0].total_cmp(&self.map[b.0]))
+            .map(|(exit, _)| exit)
+    }
+}
+
+
+/// Resource holding the Dijkstra maps built this turn, so every monster heading for the same
+/// tiles shares one field instead of searching on its own. Cleared at the start of each monster turn.
+#[derive(Default)]
+pub struct DijkstraCache {
+    maps: HashMap<(Vec<usize>, u32, String), DijkstraMap>,
+}
+
+impl DijkstraCache {
+    pub fn new() -> DijkstraCache {
+        DijkstraCache { maps: HashMap::new() }
+    }
+
+    ///drops every cached field, call whenever the map or whatever blocks it has changed
+    pub fn clear(&mut self) {
+        self.maps.clear();
+    }
+
+    ///the field flowing out from `starts`, built on first use since the last `clear`
+    pub fn get_or_build(&mut self, starts: &[usize], map: &dyn Algorithm2D, max_depth: f32) -> &DijkstraMap {
+        self.get_or_build_keyed(String::new(), starts, map, max_depth)
+    }
+
+    ///like `get_or_build`, but priced for `profile`; movers sharing a profile share the field
+    pub fn get_or_build_profiled(&mut self, starts: &[usize], map: &Map, profile: &MovementProfile, max_depth: f32) -> &DijkstraMap {
+        self.get_or_build_keyed(profile.name.clone(), starts, &map.with_profile(profile), max_depth)
+    }
+
+    fn get_or_build_keyed(&mut self, profile: String, starts: &[usize], map: &dyn Algorithm2D, max_depth: f32) -> &DijkstraMap {
+        let mut key_starts = starts.to_vec();
+        key_starts.sort_unstable();
+        key_starts.dedup();
+        self.maps
+            .entry((key_starts, max_depth.to_bits(), profile))
+            .or_insert_with(|| DijkstraMap::new(starts, map, max_depth))
+    }
+}
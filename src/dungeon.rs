@@ -0,0 +1,237 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+use specs::prelude::*;
+
+use super::{Map, MapStyle, TileType, Position, OtherLevelPosition, Viewshed, Monster, AiState, Player, EventLog, GameRng, Point, BuiltLevel, PrefabLibrary, DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT, Noises, spawner, delete_with_belongings,
+            BlocksTile, MovementProfile, MoveCost};
+
+
+/// Resource holding the levels the player has left behind, keyed by depth. When a level is kept,
+/// its entities stay in the world with an `OtherLevelPosition` instead of a `Position`.
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Dungeon {
+    pub levels: HashMap<i32, Map>,
+    /// false to throw a level away on leaving it, going back up then finds a freshly generated one
+    pub keep_levels: bool,
+    /// how new levels are laid out, None picks a style at random for each one
+    pub style: Option<MapStyle>,
+    /// size of newly generated levels, in tiles
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Default for Dungeon {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Dungeon {
+    pub fn new() -> Dungeon {
+        Dungeon {
+            levels: HashMap::new(),
+            keep_levels: true,
+            style: None,
+            width: DEFAULT_MAP_WIDTH,
+            height: DEFAULT_MAP_HEIGHT,
+        }
+    }
+}
+
+
+///moves the player to the level at `depth`, restoring it if it was visited before and generating it otherwise.
+///going down puts the player on the up stairs, going up on the down stairs
+pub fn change_level(ecs: &mut World, depth: i32) {
+    let current_depth = ecs.fetch::<Map>().depth;
+    let followers = leave_level(ecs);
+    // anything still ringing out was on the level just left
+    ecs.write_resource::<Noises>().noises.clear();
+
+    let stored = ecs.write_resource::<Dungeon>().levels.remove(&depth);
+    let map = match stored {
+        Some(mut map) => {
+            // what stood where is stale, and missing altogether if the level came out of a save
+            map.tile_content = vec![Vec::new(); map.tiles.len()];
+            enter_stored_level(ecs, depth);
+            map
+        }
+        None => {
+            let mut level = build_level(ecs, depth);
+            spawner::spawn_level_doors(ecs, &mut level);
+            spawner::spawn_level_torches(ecs, &level);
+            spawner::spawn_level_monsters(ecs, &level.spawn_points, depth);
+            level.map
+        }
+    };
+
+    let arrive_on = if depth > current_depth { TileType::UpStairs } else { TileType::DownStairs };
+    let (player_x, player_y) = match map.find_tile(arrive_on) {
+        Some(idx) => (idx as i32 % map.width, idx as i32 / map.width),
+        None => map.rooms[0].center(),
+    };
+    ecs.insert(map);
+    place_player(ecs, player_x, player_y);
+    gather_followers(ecs, &followers);
+
+    let message = if depth > current_depth {
+        format!("You descend to level {}.", depth)
+    } else {
+        format!("You climb up to level {}.", depth)
+    };
+    ecs.fetch_mut::<EventLog>().message(message);
+}
+
+///generates the level at `depth` in the dungeon's style (or a random one) and size, with a prefab or two
+///stamped in wherever there's solid rock for them
+pub fn build_level(ecs: &mut World, depth: i32) -> BuiltLevel {
+    const MAX_PREFABS : usize = 2;
+
+    let (style, size) = {
+        let dungeon = ecs.fetch::<Dungeon>();
+        (dungeon.style, Point::new(dungeon.width, dungeon.height))
+    };
+    let prefabs = ecs.fetch::<PrefabLibrary>();
+    let mut rng = ecs.write_resource::<GameRng>();
+    let style = style.unwrap_or_else(|| MapStyle::random(&mut rng, depth));
+    let mut level = style.builder().build_map(&mut rng, depth, size);
+    if !prefabs.prefabs.is_empty() {
+        let count = rng.gen_range(0..=MAX_PREFABS);
+        prefabs.stamp_prefabs(&mut level, &mut rng, count);
+    }
+    level
+}
+
+///packs the current level away (or drops it if levels aren't kept); everything but the player, and the followers
+///that could see it go, goes with it. returns those followers
+fn leave_level(ecs: &mut World) -> Vec<Entity> {
+    let map = (*ecs.fetch::<Map>()).clone();
+    let keep = ecs.fetch::<Dungeon>().keep_levels;
+    let player_pos = *ecs.fetch::<Point>();
+
+    let (followers, left_behind) = {
+        let entities = ecs.entities();
+        let positions = ecs.read_storage::<Position>();
+        let players = ecs.read_storage::<Player>();
+        let monsters = ecs.read_storage::<Monster>();
+        let viewsheds = ecs.read_storage::<Viewshed>();
+        (&entities, &positions, !&players).join()
+            .map(|(entity, pos, _)| (entity, pos.x, pos.y))
+            .partition::<Vec<(Entity, i32, i32)>, _>(|(entity, _, _)| {
+                monsters.get(*entity).is_some_and(|monster| monster.state == AiState::Follow)
+                    && viewsheds.get(*entity).is_some_and(|viewshed| viewshed.visible_tiles.contains(player_pos))
+            })
+    };
+    let followers = followers.into_iter().map(|(entity, _, _)| entity).collect();
+
+    if !keep {
+        for (entity, _, _) in left_behind {
+            delete_with_belongings(ecs, entity);
+        }
+        return followers;
+    }
+
+    {
+        let mut positions = ecs.write_storage::<Position>();
+        let mut elsewhere = ecs.write_storage::<OtherLevelPosition>();
+        let mut monsters = ecs.write_storage::<Monster>();
+        for (entity, x, y) in left_behind {
+            positions.remove(entity);
+            elsewhere.insert(entity, OtherLevelPosition { x, y, depth: map.depth }).expect("Unable to insert");
+            // whatever it remembered about the player is out of date by the time it's back, so it gives up and goes home
+            if let Some(monster) = monsters.get_mut(entity) {
+                if matches!(monster.state, AiState::Chase | AiState::Search { .. } | AiState::Flee) {
+                    monster.state = AiState::ReturnHome;
+                }
+            }
+        }
+    }
+    ecs.write_resource::<Dungeon>().levels.insert(map.depth, map);
+    followers
+}
+
+///puts the entities that were left on the level at `depth` back where they were
+fn enter_stored_level(ecs: &mut World, depth: i32) {
+    let entities = ecs.entities();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut elsewhere = ecs.write_storage::<OtherLevelPosition>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+
+    let returning : Vec<(Entity, i32, i32)> = (&entities, &elsewhere).join()
+        .filter(|(_, other)| other.depth == depth)
+        .map(|(entity, other)| (entity, other.x, other.y))
+        .collect();
+    for (entity, x, y) in returning {
+        elsewhere.remove(entity);
+        positions.insert(entity, Position { x, y }).expect("Unable to insert");
+        if let Some(viewshed) = viewsheds.get_mut(entity) {
+            viewshed.dirty = true;
+        }
+    }
+}
+
+fn place_player(ecs: &mut World, x: i32, y: i32) {
+    let player_entity = *ecs.fetch::<Entity>();
+    if let Some(pos) = ecs.write_storage::<Position>().get_mut(player_entity) {
+        pos.x = x;
+        pos.y = y;
+    }
+    if let Some(viewshed) = ecs.write_storage::<Viewshed>().get_mut(player_entity) {
+        viewshed.dirty = true;
+    }
+    ecs.insert(Point::new(x, y));
+}
+
+///puts each of `followers` on the free tile closest to the player that it could walk to from there
+pub fn gather_followers(ecs: &mut World, followers: &[Entity]) {
+    let player_pos = *ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let entities = ecs.entities();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut viewsheds = ecs.write_storage::<Viewshed>();
+    let mut monsters = ecs.write_storage::<Monster>();
+    let blockers = ecs.read_storage::<BlocksTile>();
+    let profiles = ecs.read_storage::<MovementProfile>();
+
+    let start = map.xy_idx(player_pos.x, player_pos.y);
+    let mut taken : HashSet<usize> = (&entities, &positions, &blockers).join()
+        .filter(|(entity, _, _)| !followers.contains(entity))
+        .map(|(_, pos, _)| map.xy_idx(pos.x, pos.y))
+        .collect();
+    taken.insert(start);
+
+    for follower in followers {
+        let profile = profiles.get(*follower);
+        let walkable = |idx: usize| {
+            let tile = map.tiles[idx];
+            profile.map_or_else(|| tile.move_cost(), |profile| profile.cost(tile)) != MoveCost::Impassable && !map.door_closed(idx)
+        };
+        // nearest first, so it ends up right by the player
+        let mut seen = HashSet::from([start]);
+        let mut open = VecDeque::from([start]);
+        let mut spot = None;
+        while let Some(idx) = open.pop_front() {
+            if !taken.contains(&idx) { spot = Some(idx); break; }
+            let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+            for (dx, dy) in [(0, -1), (0, 1), (-1, 0), (1, 0), (-1, -1), (1, -1), (-1, 1), (1, 1)] {
+                if !map.in_bounds(x + dx, y + dy) { continue; }
+                let next = map.xy_idx(x + dx, y + dy);
+                if walkable(next) && seen.insert(next) {
+                    open.push_back(next);
+                }
+            }
+        }
+        // nowhere free at all, it squeezes in with the player
+        let spot = spot.unwrap_or(start);
+        taken.insert(spot);
+        positions.insert(*follower, Position { x: spot as i32 % map.width, y: spot as i32 / map.width }).expect("Unable to insert");
+        if let Some(viewshed) = viewsheds.get_mut(*follower) {
+            viewshed.dirty = true;
+        }
+        // its old home was somewhere else entirely
+        if let Some(monster) = monsters.get_mut(*follower) {
+            monster.home = None;
+        }
+    }
+}
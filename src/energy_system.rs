@@ -0,0 +1,88 @@
+use specs::prelude::*;
+
+use super::{Energy, MyTurn, Position};
+
+/// Energy needed to act, and what an ordinary action costs
+pub const TURN_ENERGY : i32 = 100;
+/// Energy gained each tick by anything of ordinary speed, which lets it act once every ten ticks
+pub const NORMAL_SPEED : i32 = 10;
+
+
+/// What a turn was spent on, which decides how much energy it took
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum ActionCost {
+    Move,
+    /// a step taken at a run, quicker and louder
+    Run,
+    Attack,
+    /// opening or closing a door
+    Door,
+    /// drinking, reading or otherwise using up something carried
+    UseItem,
+    /// calling out to others
+    Shout,
+    /// waiting, or anything else that gets nothing done
+    Rest,
+}
+
+impl ActionCost {
+    pub fn energy(self) -> i32 {
+        match self {
+            ActionCost::Move | ActionCost::Door | ActionCost::UseItem | ActionCost::Shout => TURN_ENERGY,
+            // a swing takes a bit longer than a step
+            ActionCost::Attack => TURN_ENERGY * 6 / 5,
+            ActionCost::Run => TURN_ENERGY * 3 / 5,
+            ActionCost::Rest => TURN_ENERGY / 2,
+        }
+    }
+}
+
+
+///works out who acts next. Time passes, everything on the level gaining its speed in energy each tick,
+///until something has `TURN_ENERGY`. Returns true if that's the player, who goes first if it has at least
+///as much energy as anyone else. Otherwise the monster with the most energy, and any tied with it, is given
+///`MyTurn` and false is returned: run the systems so it acts, then call this again. Monsters that are ready
+///act one energy level at a time, the most first, so a fast one is never kept waiting behind a slow one
+///Whatever took a turn removes its `MyTurn` and pays for it, anything still holding one here is charged for resting
+pub fn next_turn(ecs: &mut World) -> bool {
+    let player = *ecs.fetch::<Entity>();
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let mut energies = ecs.write_storage::<Energy>();
+    let mut turns = ecs.write_storage::<MyTurn>();
+
+    for (energy, _turn) in (&mut energies, turns.drain()).join() {
+        energy.spend(ActionCost::Rest);
+    }
+    turns.clear();
+    if !energies.contains(player) {
+        // a player made without one moves at the usual pace
+        energies.insert(player, Energy::new(NORMAL_SPEED)).expect("Unable to insert energy");
+    }
+
+    loop {
+        let player_energy = energies.get(player).map_or(0, |energy| energy.energy);
+        let player_ready = player_energy >= TURN_ENERGY;
+        // only what's on this level takes turns, anything left behind on another one is frozen in time
+        let ready = |(entity, energy, _pos): &(Entity, &Energy, &Position)| {
+            *entity != player && energy.ready() && (!player_ready || energy.energy > player_energy)
+        };
+        let most = (&entities, &energies, &positions).join().filter(ready).map(|(_, energy, _)| energy.energy).max();
+        if let Some(most) = most {
+            for (entity, _, _) in (&entities, &energies, &positions).join().filter(ready).filter(|(_, energy, _)| energy.energy == most) {
+                turns.insert(entity, MyTurn{}).expect("Unable to insert turn");
+            }
+            return false;
+        }
+        if player_ready { return true; }
+
+        // nothing can act yet, skip straight to the tick where the first thing can
+        let ticks = (&energies, &positions).join()
+            .map(|(energy, _pos)| energy.ticks_until_ready())
+            .min()
+            .unwrap_or(1);
+        for (energy, _pos) in (&mut energies, &positions).join() {
+            energy.energy += energy.speed.max(1) * ticks;
+        }
+    }
+}
@@ -1,11 +1,21 @@
+#[cfg(feature = "render")]
 use raylib::prelude::*;
+#[cfg(feature = "render")]
 use specs::World;
+use serde::{Serialize, Deserialize};
 
 
+#[derive(Serialize, Deserialize)]
 pub struct EventLog {
     pub log: Vec<String>
 }
 
+impl Default for EventLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl EventLog {
     pub fn new() -> EventLog{
         EventLog{log : Vec::new()}
@@ -16,16 +26,14 @@ impl EventLog {
     }
 }
 
+#[cfg(feature = "render")]
 pub fn draw_log(ecs: &World, draw: &mut RaylibDrawHandle) {
     let log = ecs.fetch::<EventLog>();
     let mut temp = log.log.clone();
     for i in 0..4 {
         let item = temp.pop();
-        match item {
-            Some(message) => {
-                draw.draw_text(&message, 0, i*10, 8, match i {0 => {Color::WHITE},_=>{Color::GRAY}});
-            }
-            None => {}
+        if let Some(message) = item {
+            draw.draw_text(&message, 0, i*10, 8, match i {0 => {Color::WHITE},_=>{Color::GRAY}});
         }
     }
 }
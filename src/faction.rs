@@ -0,0 +1,71 @@
+use std::collections::HashMap;
+
+use super::Faction;
+
+/// The player's side, and anything fighting on it
+pub const PLAYER_FACTION : &str = "player";
+/// Where anything without a `Faction` is counted
+pub const DEFAULT_FACTION : &str = "monsters";
+
+
+/// How one creature takes to another
+#[derive(PartialEq, Eq, Copy, Clone, Debug)]
+pub enum Reaction {
+    /// attacks it on sight
+    Hostile,
+    /// leaves it alone, the player talks to it instead of attacking
+    Neutral,
+    /// on the same side: allies of the player follow it around, and the player swaps places with them
+    Friendly,
+    /// pays it no mind at all, and won't make way for the player either
+    Ignore,
+}
+
+/// Resource saying how each faction reacts to each other one. A faction is always friendly to itself,
+/// and neutral to any faction the table doesn't mention
+#[derive(Clone, Debug, Default)]
+pub struct FactionTable {
+    pub reactions: HashMap<(String, String), Reaction>,
+}
+
+impl FactionTable {
+    pub fn new() -> FactionTable {
+        FactionTable { reactions: HashMap::new() }
+    }
+
+    ///the engine's own creatures: everything is out to get the player, goblins and wildlife go for each
+    ///other, and hermits keep to themselves
+    pub fn standard() -> FactionTable {
+        let mut table = FactionTable::new();
+        for faction in [DEFAULT_FACTION, "goblins", "wildlife"] {
+            table.set_mutual(PLAYER_FACTION, faction, Reaction::Hostile);
+        }
+        table.set_mutual("goblins", "wildlife", Reaction::Hostile);
+        table.set_mutual(DEFAULT_FACTION, "goblins", Reaction::Ignore);
+        table.set_mutual(DEFAULT_FACTION, "wildlife", Reaction::Ignore);
+        table
+    }
+
+    ///how `from` reacts to `to`, without changing how `to` reacts back
+    pub fn set(&mut self, from: &str, to: &str, reaction: Reaction) {
+        self.reactions.insert((from.to_string(), to.to_string()), reaction);
+    }
+
+    ///how `a` and `b` react to each other
+    pub fn set_mutual(&mut self, a: &str, b: &str, reaction: Reaction) {
+        self.set(a, b, reaction);
+        self.set(b, a, reaction);
+    }
+
+    pub fn reaction(&self, from: &str, to: &str) -> Reaction {
+        if from == to { return Reaction::Friendly; }
+        self.reactions.get(&(from.to_string(), to.to_string())).copied().unwrap_or(Reaction::Neutral)
+    }
+
+    ///how something in faction `from` reacts to something in faction `to`, either counting as
+    ///`DEFAULT_FACTION` if it has none
+    pub fn between(&self, from: Option<&Faction>, to: Option<&Faction>) -> Reaction {
+        let name = |faction: Option<&Faction>| faction.map_or(DEFAULT_FACTION, |faction| faction.name.as_str()).to_string();
+        self.reaction(&name(from), &name(to))
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use super::{Point, Map, FovAlgorithm};
+
+
+/// The tiles a field of view reaches, held both as a bit per map tile, so asking about a tile is a
+/// single lookup, and as a list for walking through them. Clearing keeps the memory for next time.
+#[derive(Default, Debug)]
+pub struct VisibleTiles {
+    width: i32,
+    height: i32,
+    bits: Vec<u64>,
+    tiles: Vec<Point>,
+}
+
+impl VisibleTiles {
+    pub fn new() -> VisibleTiles {
+        VisibleTiles { width: 0, height: 0, bits: Vec::new(), tiles: Vec::new() }
+    }
+
+    ///empties the set and sizes it for a `width` by `height` map
+    pub fn reset(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+        self.bits.clear();
+        self.bits.resize(((width * height) as usize).div_ceil(64), 0);
+        self.tiles.clear();
+    }
+
+    fn index(&self, point: Point) -> Option<usize> {
+        if point.x < 0 || point.x >= self.width || point.y < 0 || point.y >= self.height {
+            return None;
+        }
+        Some((point.y * self.width + point.x) as usize)
+    }
+
+    ///adds `point`, returns false if it was already in or is off the map
+    pub fn insert(&mut self, point: Point) -> bool {
+        let Some(idx) = self.index(point) else { return false; };
+        let (word, bit) = (idx / 64, 1u64 << (idx % 64));
+        if self.bits[word] & bit != 0 { return false; }
+        self.bits[word] |= bit;
+        self.tiles.push(point);
+        true
+    }
+
+    pub fn contains(&self, point: Point) -> bool {
+        self.index(point).is_some_and(|idx| self.bits[idx / 64] & (1u64 << (idx % 64)) != 0)
+    }
+
+    ///every tile in the set, in the order they were added
+    pub fn iter(&self) -> impl Iterator<Item = Point> + '_ {
+        self.tiles.iter().copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.tiles.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.tiles.is_empty()
+    }
+}
+
+impl Clone for VisibleTiles {
+    fn clone(&self) -> Self {
+        VisibleTiles { width: self.width, height: self.height, bits: self.bits.clone(), tiles: self.tiles.clone() }
+    }
+
+    // copying into an existing set reuses its buffers
+    fn clone_from(&mut self, source: &Self) {
+        self.width = source.width;
+        self.height = source.height;
+        self.bits.clone_from(&source.bits);
+        self.tiles.clone_from(&source.tiles);
+    }
+}
+
+
+/// Resource holding the fields of view worked out on the current map, keyed by where they were seen
+/// from. Anything standing still, or coming back to a tile already looked from, gets its view for the
+/// price of a copy. Everything is thrown out once the map's revision changes.
+#[derive(Default)]
+pub struct FovCache {
+    revision: u64,
+    fields: HashMap<(Point, i32, FovAlgorithm), VisibleTiles>,
+    /// sets from thrown out fields, kept so new ones don't have to allocate
+    spare: Vec<VisibleTiles>,
+}
+
+impl FovCache {
+    /// once this many fields are held they're all thrown out, so wandering around doesn't grow it forever
+    const MAX_FIELDS : usize = 1024;
+
+    pub fn new() -> FovCache {
+        FovCache { revision: 0, fields: HashMap::new(), spare: Vec::new() }
+    }
+
+    ///drops every cached field, keeping their buffers for reuse
+    pub fn clear(&mut self) {
+        self.spare.extend(self.fields.drain().map(|(_, field)| field));
+    }
+
+    ///what `algorithm` sees from `center` out to `range` on `map`, worked out on first use since the map last changed
+    pub fn get_or_compute(&mut self, center: Point, range: i32, algorithm: FovAlgorithm, map: &Map) -> &VisibleTiles {
+        let key = (center, range, algorithm);
+        if self.revision != map.revision() {
+            self.clear();
+            self.revision = map.revision();
+        } else if self.fields.len() >= Self::MAX_FIELDS && !self.fields.contains_key(&key) {
+            self.clear();
+        }
+        let spare = &mut self.spare;
+        self.fields.entry(key).or_insert_with(|| {
+            let mut field = spare.pop().unwrap_or_default();
+            algorithm.field_of_view_into(center, range, map, &mut field);
+            field
+        })
+    }
+}
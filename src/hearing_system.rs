@@ -0,0 +1,121 @@
+use std::collections::{BinaryHeap, HashMap};
+
+use specs::prelude::*;
+
+use super::{Map, Point, Monster, AiState, Hearing, Position, Name, EventLog, Faction, FactionTable, Reaction};
+
+/// How loud things are, roughly how many tiles of open floor they carry across
+pub const COMBAT_NOISE : i32 = 10;
+pub const DOOR_NOISE : i32 = 6;
+pub const RUN_NOISE : i32 = 7;
+/// How much quieter a noise gets going through a tile of solid rock, or a shut door
+const WALL_DAMPING : i32 = 5;
+const DOOR_DAMPING : i32 = 3;
+/// A sleeping monster only wakes to a noise this much louder than it would notice awake
+const SLEEP_DAMPING : i32 = 3;
+
+
+/// Something the player did, or was part of, that could be heard
+#[derive(Clone, Copy, Debug)]
+pub struct Noise {
+    pub at: Point,
+    pub loudness: i32,
+}
+
+/// Resource collecting this turn's noises until `HearingSystem` gets to them
+#[derive(Default)]
+pub struct Noises {
+    pub noises: Vec<Noise>,
+}
+
+impl Noises {
+    pub fn new() -> Noises {
+        Noises { noises: Vec::new() }
+    }
+
+    pub fn make(&mut self, at: Point, loudness: i32) {
+        self.noises.push(Noise { at, loudness });
+    }
+}
+
+
+///how loud a noise of `loudness` made at `origin` still is on every tile it reaches. it quietens by one
+///each step across open ground and by a good deal more through rock or a shut door
+pub fn noise_levels(map: &Map, origin: Point, loudness: i32) -> HashMap<usize, i32> {
+    let mut levels = HashMap::new();
+    if loudness <= 0 || !map.in_bounds(origin.x, origin.y) { return levels; }
+    let start = map.xy_idx(origin.x, origin.y);
+    levels.insert(start, loudness);
+    // loudest first, so each tile is settled by the loudest way the noise gets there
+    let mut open = BinaryHeap::new();
+    open.push((loudness, start));
+
+    while let Some((level, idx)) = open.pop() {
+        if levels.get(&idx).is_some_and(|best| *best > level) { continue; }
+        let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+        for (dx, dy) in [(0, -1), (0, 1), (-1, 0), (1, 0), (-1, -1), (1, -1), (-1, 1), (1, 1)] {
+            if !map.in_bounds(x + dx, y + dy) { continue; }
+            let next = map.xy_idx(x + dx, y + dy);
+            let damping = if map.tiles[next].def().solid {
+                WALL_DAMPING
+            } else if map.door_closed(next) {
+                DOOR_DAMPING
+            } else {
+                1
+            };
+            let left = level - damping;
+            if left <= 0 || levels.get(&next).is_some_and(|best| *best >= left) { continue; }
+            levels.insert(next, left);
+            open.push((left, next));
+        }
+    }
+    levels
+}
+
+
+/// Lets monsters with `Hearing` notice this turn's noises: they wake up if they were asleep, and those hostile
+/// to the player head for where it came from
+pub struct HearingSystem {}
+
+impl<'a> System<'a> for HearingSystem {
+    type SystemData = ( ReadExpect<'a, Map>,
+                        WriteExpect<'a, Noises>,
+                        ReadStorage<'a, Hearing>,
+                        WriteStorage<'a, Monster>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, Name>,
+                        WriteExpect<'a, EventLog>,
+                        Entities<'a>,
+                        ReadExpect<'a, Entity>,
+                        ReadStorage<'a, Faction>,
+                        ReadExpect<'a, FactionTable>);
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (map, mut noises, hearing, mut monsters, positions, names, mut log, entities, player, factions, table) = data;
+
+        for noise in noises.noises.drain(..) {
+            let levels = noise_levels(&map, noise.at, noise.loudness);
+            for (entity, hearing, monster, pos, name) in (&entities, &hearing, &mut monsters, &positions, names.maybe()).join() {
+                let idx = map.xy_idx(pos.x, pos.y);
+                let Some(level) = levels.get(&idx) else { continue; };
+                let asleep = monster.state == AiState::Asleep;
+                if *level < hearing.threshold + if asleep { SLEEP_DAMPING } else { 0 } { continue; }
+
+                if asleep && map.visible_tiles[idx] {
+                    if let Some(name) = name {
+                        log.message(format!("{} wakes up.", name.name));
+                    }
+                }
+                if table.between(factions.get(entity), factions.get(*player)) != Reaction::Hostile {
+                    if asleep { monster.state = AiState::Idle; }
+                    continue;
+                }
+                monster.known_player_location = (noise.at.x, noise.at.y);
+                // a hurt monster keeps running, anything else goes to see what that was
+                if monster.state != AiState::Flee {
+                    monster.state = AiState::Chase;
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,78 @@
+mod map;
+mod tiles;
+mod player;
+mod components;
+mod rect;
+mod visibility_system;
+mod symmetric_fov;
+mod fov_cache;
+mod lighting_system;
+mod energy_system;
+mod hearing_system;
+mod faction;
+mod monster_ai_system;
+mod behaviour_tree;
+mod behaviour_system;
+mod event_log;
+mod state;
+mod astar;
+mod map_indexing_system;
+mod melee_combat_system;
+mod damage_system;
+mod color;
+mod spawner;
+mod builder;
+mod rng;
+mod saveload_system;
+mod replay;
+mod base_map;
+mod dijkstra;
+mod dungeon;
+mod map_builders;
+mod camera;
+#[cfg(feature = "render")]
+mod tileset;
+
+
+pub use map::*;
+pub use tiles::*;
+pub use player::*;
+pub use components::*;
+pub use rect::*;
+pub use visibility_system::*;
+pub use symmetric_fov::*;
+pub use fov_cache::*;
+pub use lighting_system::*;
+pub use energy_system::*;
+pub use hearing_system::*;
+pub use faction::*;
+pub use monster_ai_system::*;
+pub use behaviour_tree::*;
+pub use behaviour_system::*;
+pub use event_log::*;
+pub use state::*;
+pub use astar::*;
+pub use map_indexing_system::*;
+pub use melee_combat_system::*;
+pub use damage_system::*;
+pub use color::*;
+pub use spawner::*;
+pub use builder::*;
+pub use rng::*;
+pub use saveload_system::*;
+pub use replay::*;
+pub use base_map::*;
+pub use dijkstra::*;
+pub use dungeon::*;
+pub use map_builders::*;
+pub use camera::*;
+#[cfg(feature = "render")]
+pub use tileset::*;
+
+use specs::prelude::*;
+#[cfg(feature = "render")]
+use raylib::prelude::*;
+
+
+pub const TILE_SIZE : i32 = 8;
+pub const SCALE : f32 = 1.5;
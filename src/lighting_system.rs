@@ -0,0 +1,34 @@
+use specs::prelude::*;
+use super::{Map, Position, LightSource, Point, FovAlgorithm, FovCache};
+
+pub struct LightingSystem {}
+
+impl<'a> System<'a> for LightingSystem {
+    type SystemData = ( WriteExpect<'a, Map>,
+                        WriteExpect<'a, FovCache>,
+                        ReadStorage<'a, Position>,
+                        ReadStorage<'a, LightSource>);
+
+    fn run(&mut self, data : Self::SystemData) {
+        let (mut map, mut cache, position, lights) = data;
+
+        // everything moves around, so the light is worked out from scratch, in last turn's buffer
+        let mut light = std::mem::take(&mut map.light);
+        light.clear();
+        light.resize(map.tiles.len(), [0.0; 3]);
+        for (pos, source) in (&position, &lights).join() {
+            let color = [source.color.r, source.color.g, source.color.b].map(|c| c as f32 / 255.0 * source.intensity);
+            let reach = source.range as f32 + 1.0;
+            // the light goes wherever the source could see. most lights never move, so this is nearly always cached
+            for tile in cache.get_or_compute(Point::new(pos.x, pos.y), source.range, FovAlgorithm::Scanning, &map).iter() {
+                let falloff = 1.0 - Map::distance2d_pythagoras(pos.x, pos.y, tile.x, tile.y) / reach;
+                if falloff <= 0.0 { continue; }
+                let lit_tile = &mut light[map.xy_idx(tile.x, tile.y)];
+                for (lit, added) in lit_tile.iter_mut().zip(color) {
+                    *lit += added * falloff;
+                }
+            }
+        }
+        map.light = light;
+    }
+}
@@ -1,150 +1,351 @@
-mod map;
-mod player;
-mod components;
-mod rect;
-mod visibility_system;
-mod monster_ai_system;
-mod event_log;
-mod state;
-mod astar;
-mod map_indexing_system;
-mod melee_combat_system;
-mod damage_system;
-
-
-pub use map::*;
-pub use player::*;
-pub use components::*;
-pub use rect::*;
-pub use visibility_system::*;
-pub use monster_ai_system::*;
-pub use event_log::*;
-pub use state::*;
-pub use astar::*;
-pub use map_indexing_system::*;
-pub use melee_combat_system::*;
-pub use damage_system::*;
-
-use rand::{thread_rng, Rng};
-use specs::prelude::*;
+use std::path::Path;
+
+use my_engine::*;
+
+#[cfg(not(feature = "render"))]
+use rand::Rng;
+#[cfg(feature = "render")]
 use raylib::prelude::*;
+use specs::prelude::*;
+
+
+/// How many turns a headless run plays before giving up
+#[cfg(not(feature = "render"))]
+pub const HEADLESS_TURNS : usize = 1000;
 
 
-pub const TILE_SIZE : i32 = 8;
-pub const SCALE : f32 = 1.5;
+/// Smallest map the generators are asked to fill
+const MIN_MAP_SIZE : i32 = 12;
+
+
+/// What was asked for on the command line
+struct Options {
+    seed: Option<u64>,
+    map_style: Option<MapStyle>,
+    map_size: Option<(i32, i32)>,
+    /// generate this many levels of every style, check they're fully connected and quit
+    check_maps: Option<u64>,
+    record: Option<String>,
+    replay: Option<String>,
+    /// replayed commands per second when replaying in a window
+    #[cfg_attr(not(feature = "render"), allow(dead_code))]
+    replay_speed: f64,
+}
+
+impl Options {
+    ///reads `--seed <number>`, `--map <style>`, `--size <width>x<height>`, `--check-maps <count>`, `--record <file>`, `--replay <file>` and `--replay-speed <commands per second>`
+    fn from_args() -> Options {
+        let args : Vec<String> = std::env::args().collect();
+        let value = |flag: &str| {
+            let pos = args.iter().position(|a| a == flag)?;
+            args.get(pos + 1).cloned()
+        };
+
+        let seed = value("--seed").and_then(|s| match s.parse::<u64>() {
+            Ok(seed) => Some(seed),
+            Err(_) => {
+                eprintln!("--seed needs a whole number, using a random seed instead");
+                None
+            }
+        });
+        let map_style = value("--map").and_then(|s| match s.parse::<MapStyle>() {
+            Ok(style) => Some(style),
+            Err(e) => {
+                eprintln!("{}, picking a style for each level instead", e);
+                None
+            }
+        });
+        let map_size = value("--size").and_then(|s| match parse_size(&s) {
+            Some(size) => Some(size),
+            None => {
+                eprintln!("--size needs a width and height like 120x80, both at least {}, using the default size instead", MIN_MAP_SIZE);
+                None
+            }
+        });
+        let replay_speed = value("--replay-speed").and_then(|s| s.parse::<f64>().ok()).unwrap_or(10.0);
+
+        Options { seed, map_style, map_size, check_maps: value("--check-maps").and_then(|s| s.parse().ok()), record: value("--record"), replay: value("--replay"), replay_speed }
+    }
+}
+
+///parses `<width>x<height>`
+fn parse_size(s: &str) -> Option<(i32, i32)> {
+    let (width, height) = s.split_once('x')?;
+    let size = (width.trim().parse::<i32>().ok()?, height.trim().parse::<i32>().ok()?);
+    (size.0 >= MIN_MAP_SIZE && size.1 >= MIN_MAP_SIZE).then_some(size)
+}
 
 
 fn main() {
-    let mut gs = State {
-        ecs: World::new(),
+    let options = Options::from_args();
+    if let Some(count) = options.check_maps {
+        if !check_maps(count, &options) {
+            std::process::exit(1);
+        }
+        return;
+    }
+    if let Some(path) = &options.replay {
+        replay(path, &options);
+        return;
+    }
+
+    let mut builder = GameBuilder::new().with_prefabs(load_prefabs()).with_behaviours(load_behaviours());
+    if let Some(seed) = options.seed {
+        builder = builder.with_seed(seed);
+    }
+    if let Some(style) = options.map_style {
+        builder = builder.with_map_style(style);
+    }
+    if let Some((width, height)) = options.map_size {
+        builder = builder.with_map_size(width, height);
+    }
+    let mut gs = start_game(builder);
+
+    println!("seed {}", gs.ecs.fetch::<GameRng>().seed());
+    run(&mut gs);
+
+    if let (Some(recording), Some(path)) = (finish_recording(&gs.ecs), record_path(&options)) {
+        if let Err(e) = recording.save(path) {
+            eprintln!("{}: {}", path, e);
+        }
+    }
+}
+
+///the prefabs in the prefab directory, none if there isn't one. A broken prefab file stops the game,
+///it's better the designer hears about it straight away
+fn load_prefabs() -> PrefabLibrary {
+    if !Path::new(PREFAB_DIR).is_dir() {
+        return PrefabLibrary::new();
+    }
+    match PrefabLibrary::load_dir(PREFAB_DIR) {
+        Ok(library) => library,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+///the behaviour trees in the behaviour directory, none if there isn't one. Like prefabs, a broken file stops the game
+fn load_behaviours() -> BehaviourLibrary {
+    if !Path::new(BEHAVIOUR_DIR).is_dir() {
+        return BehaviourLibrary::new();
+    }
+    match BehaviourLibrary::load_dir(BEHAVIOUR_DIR) {
+        Ok(library) => library,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+///builds `count` levels of every style (or just the one asked for), starting from the seed asked for,
+///and reports any that aren't fully connected. Returns true if all of them are
+fn check_maps(count: u64, options: &Options) -> bool {
+    let styles = match options.map_style {
+        Some(style) => vec![style],
+        None => MapStyle::ALL.to_vec(),
     };
+    let (width, height) = options.map_size.unwrap_or((DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT));
+    let first = options.seed.unwrap_or(0);
+    let prefabs = load_prefabs();
 
-    gs.ecs.register::<Position>();
-    gs.ecs.register::<Renderable>();
-    gs.ecs.register::<Player>();
-    gs.ecs.register::<Viewshed>();
-    gs.ecs.register::<Monster>();
-    gs.ecs.register::<Name>();
-    gs.ecs.register::<BlocksTile>();
-    gs.ecs.register::<CombatStats>();
-    gs.ecs.register::<WantsToMelee>();
-    gs.ecs.register::<SufferDamage>();
-
-    
-    gs.ecs.insert(RunState::PreRun);
-
-    let map = map::Map::new_map_rooms_and_corridors();
-    let (player_x, player_y) = map.rooms[0].center();
-
-    let mut rng = thread_rng();
-    for (i,room) in map.rooms.iter().skip(1).enumerate() {
-        let (x,y) = room.center();
-        let sprite : u8;
-        let name : String;
-        let roll = rng.gen_range(1..3);
-        match roll {
-            1 => { sprite = 13; name = "beholder".to_string()}
-            _ => { sprite = 23; name = "motherfuckingcrab".to_string()}
+    let mut failures = 0;
+    for style in styles {
+        for seed in first..first + count {
+            let mut rng = GameRng::seeded(seed);
+            let mut level = style.builder().build_map(&mut rng, 2, Point::new(width, height));
+            prefabs.stamp_prefabs(&mut level, &mut rng, 2);
+            let report = check_connectivity(&level);
+            if !report.is_connected() {
+                failures += 1;
+                eprintln!("{} seed {}: {}", style, seed, report);
+            }
         }
-        gs.ecs.create_entity()
-            .with(Position{ x, y })
-            .with(Renderable{
-                index: sprite,
-                color: Color::WHITE,
-            })
-            .with(Viewshed{ visible_tiles : Vec::new(), range: 8, dirty: true })
-            .with(Monster{ seen_player: false, known_player_location : (0,0)})
-            .with(Name{ name: format!("{} #{}", &name, i)})
-            .with(BlocksTile{})
-            .with(CombatStats{ max_hp: 16, hp: 16, defense: 1, power: 4 })
-            .build();
-    }
-    
-    gs.ecs.insert(Point::new(player_x, player_y));
-    gs.ecs.insert(map);
-
-    let log = EventLog::new();
-    gs.ecs.insert(log);
-
-    
-    let player_entity = gs.ecs
-        .create_entity()
-        .with(Position { x: player_x, y: player_y })
-        .with(Renderable {
-            index: 8,
-            color: Color::WHITE,
-        })
-        .with(Player{health : 100})
-        .with(Viewshed{visible_tiles: Vec::new(), range : 8, dirty: true})
-        .with(Name{name: "Player".to_string()})
-        .with(CombatStats{max_hp: 30, hp: 30, defense: 2, power: 5})
-        .build();
+        println!("checked {} {} levels", count, style);
+    }
+    failures == 0
+}
 
-    gs.ecs.insert(player_entity);
+///windowed games always leave a replay behind, headless ones only when asked to
+fn record_path(options: &Options) -> Option<&str> {
+    match &options.record {
+        Some(path) => Some(path),
+        None if cfg!(feature = "render") => Some(REPLAY_PATH),
+        None => None,
+    }
+}
 
-    
+///plays back a recording and checks the world ends up the same as when it was recorded
+fn replay(path: &str, options: &Options) {
+    let recording = match Recording::load(path) {
+        Ok(recording) => recording,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    let mut replay = Replay::new(recording);
+    let mut builder = GameBuilder::new().with_prefabs(load_prefabs()).with_behaviours(load_behaviours()).with_seed(replay.seed());
+    if let Some(style) = replay.map_style() {
+        builder = builder.with_map_style(style);
+    }
+    if let Some((width, height)) = replay.map_size() {
+        builder = builder.with_map_size(width, height);
+    }
+    let mut gs = builder.build();
+
+    println!("replaying {} with seed {}", path, replay.seed());
+    if !run_replay(&mut gs, &mut replay, options.replay_speed) {
+        std::process::exit(1);
+    }
+}
+
+///prints whether the replay matched, returns true if it did
+fn report_replay(replay: &Replay, gs: &State) -> bool {
+    match replay.verify(&gs.ecs) {
+        Ok(()) => {
+            println!("replay verified, world hash {:016x}", world_hash(&gs.ecs));
+            true
+        }
+        Err((expected, actual)) => {
+            eprintln!("replay diverged: expected world hash {:016x}, got {:016x}", expected, actual);
+            false
+        }
+    }
+}
+
+///picks up the saved game if there is one, otherwise starts a new one
+#[cfg(feature = "render")]
+fn start_game(builder: GameBuilder) -> State {
+    if !does_save_exist(SAVE_PATH) {
+        return builder.build();
+    }
+    match builder.load(SAVE_PATH) {
+        Ok(gs) => gs,
+        Err(e) => {
+            eprintln!("{}: {}", SAVE_PATH, e);
+            eprintln!("move or delete it to start a new game");
+            std::process::exit(1);
+        }
+    }
+}
+
+///headless games are always fresh so that a seed alone reproduces them
+#[cfg(not(feature = "render"))]
+fn start_game(builder: GameBuilder) -> State {
+    builder.build()
+}
 
-    let (mut rl, thread) = raylib::init()
-        .size(MAPWIDTH as i32 * (TILE_SIZE as f32 * SCALE) as i32,  MAPHEIGHT as i32 * (TILE_SIZE as f32 * SCALE) as i32)
+
+///the window shows a default sized map at once; bigger maps scroll, see `Camera`
+#[cfg(feature = "render")]
+fn open_window() -> (RaylibHandle, RaylibThread, Vec<Texture2D>) {
+    let tile = Camera::tile_pixels() as i32;
+    let (rl, thread) = raylib::init()
+        .size(DEFAULT_MAP_WIDTH * tile, DEFAULT_MAP_HEIGHT * tile)
+        .resizable()
         .title("RogueLike")
         .build();
 
-    
-    let (_image,tileset,mut rl,thread) = load_tile_set(rl,thread, "tilemap2.png");
+    let (_image,tileset,rl,thread) = load_tile_set(rl,thread, "tilemap2.png");
+    (rl, thread, tileset)
+}
+
+#[cfg(feature = "render")]
+fn run(gs: &mut State) {
+    let (mut rl, thread, tileset) = open_window();
 
     while !rl.window_should_close() {
-        
+
         gs.tick(&mut rl,thread.clone(), &tileset)
-        
+
+    }
+    gs.finish_turn();
+
+    // save on quit, unless the player died, in which case the run is over for good
+    let player_entity = *gs.ecs.fetch::<Entity>();
+    let alive = gs.ecs.read_storage::<CombatStats>().get(player_entity).is_some_and(|s| s.hp > 0);
+    let result = if alive { save_game(&gs.ecs, SAVE_PATH) } else { delete_save(SAVE_PATH) };
+    if let Err(e) = result {
+        eprintln!("{}: {}", SAVE_PATH, e);
     }
 }
 
+///shows the replay in a window, `+` and `-` speed it up and slow it down, and the title says how far along it is.
+///if the window is closed early the rest is played headlessly so it can still be verified
+#[cfg(feature = "render")]
+fn run_replay(gs: &mut State, replay: &mut Replay, speed: f64) -> bool {
+    let (mut rl, thread, tileset) = open_window();
+    let mut speed = speed.max(0.1);
+    let mut last_action = rl.get_time();
+    let mut verified = None;
+    let mut title = String::new();
 
+    while !rl.window_should_close() {
+        if rl.is_key_pressed(KeyboardKey::KEY_EQUAL) || rl.is_key_pressed(KeyboardKey::KEY_KP_ADD) { speed *= 2.0; }
+        if rl.is_key_pressed(KeyboardKey::KEY_MINUS) || rl.is_key_pressed(KeyboardKey::KEY_KP_SUBTRACT) { speed = (speed / 2.0).max(0.1); }
+
+        let waiting = *gs.ecs.fetch::<RunState>() == RunState::AwaitingInput;
+        let mut action = None;
+        if waiting && rl.get_time() - last_action >= 1.0 / speed {
+            action = replay.next_action();
+            last_action = rl.get_time();
+        }
+        if waiting && action.is_none() && replay.is_finished() && verified.is_none() {
+            verified = Some(report_replay(replay, gs));
+        }
 
+        let (played, total) = replay.progress();
+        let progress = format!("RogueLike replay {}/{} at {} commands a second", played, total, speed);
+        if progress != title {
+            rl.set_window_title(&thread, &progress);
+            title = progress;
+        }
 
-///loads the tile set vector from a specified path
-fn load_tile_set(mut rl:RaylibHandle, thread: RaylibThread, path: &str) -> (Image,Vec<Texture2D>,RaylibHandle, RaylibThread) {
-	  let image: Image = Image::load_image(path).unwrap();
-	  let mut tileset: Vec<Texture2D> = Vec::new();
-    
-    let width = image.width()/TILE_SIZE;
-    let height = image.height()/TILE_SIZE;
+        gs.step(action);
+        gs.draw(&mut rl, thread.clone(), &tileset);
+    }
 
-	  let mut y:u16 = 0;
-	  let mut x:u16 = 0;
-	  //add tiles to tilemap vector
-	  for _i in 0..width*height {
-        if x >(width-1) as u16{
-    	  x = 0;
-    	  y += 1
+    match verified {
+        Some(matched) => matched,
+        None => {
+            replay.run_headless(gs);
+            gs.finish_turn();
+            report_replay(replay, gs)
         }
-        let mut temp: Image = image.clone();
-        //crops the source image to the current tile that we want to source
-        temp.crop(Rectangle::new( (x*TILE_SIZE as u16) as f32,(y*TILE_SIZE as u16) as f32,TILE_SIZE as f32, TILE_SIZE  as f32));
-        //adds this to the tilemap vector
-        tileset.push(rl.load_texture_from_image(&thread,&temp).unwrap());
-        x += 1;
-	  }
-	  return (image, tileset,rl,thread)
+    }
+}
+
+#[cfg(not(feature = "render"))]
+fn run_replay(gs: &mut State, replay: &mut Replay, _speed: f64) -> bool {
+    replay.run_headless(gs);
+    gs.finish_turn();
+    report_replay(replay, gs)
 }
 
+///plays a game with no window: the player wanders randomly, taking any stairs down it stumbles on,
+///until it dies or runs out of turns
+#[cfg(not(feature = "render"))]
+fn run(gs: &mut State) {
+    const DIRECTIONS : [(i32, i32); 8] = [ (0, -1), (1, -1), (1, 0), (1, 1), (0, 1), (-1, 1), (-1, 0), (-1, -1) ];
+    // the wanderer gets its own rng so the game's stream only depends on the commands, like a real player
+    let mut rng = GameRng::seeded(gs.ecs.fetch::<GameRng>().seed());
+    for _ in 0..HEADLESS_TURNS {
+        let (dx, dy) = DIRECTIONS[rng.gen_range(0..DIRECTIONS.len())];
+        let on_stairs = {
+            let map = gs.ecs.fetch::<Map>();
+            let pos = gs.ecs.fetch::<Point>();
+            map.tiles[map.xy_idx(pos.x, pos.y)] == TileType::DownStairs
+        };
+        gs.play_turn(if on_stairs { PlayerAction::Descend } else { PlayerAction::Move { dx, dy } });
+
+        let player_entity = *gs.ecs.fetch::<Entity>();
+        let dead = gs.ecs.read_storage::<CombatStats>().get(player_entity).is_none_or(|s| s.hp < 1);
+        if dead { break; }
+    }
+    for message in gs.ecs.fetch::<EventLog>().log.iter() {
+        println!("{}", message);
+    }
+}
@@ -1,31 +1,97 @@
+#[cfg(feature = "render")]
 use raylib::prelude::*;
-use specs::{Entity, Join, World, WorldExt};
+use serde::{Serialize, Deserialize};
+use specs::Entity;
+#[cfg(feature = "render")]
+use specs::World;
 use std::cmp::{min,max};
-use rand::Rng;
-use crate::Viewshed;
+use std::sync::atomic::{AtomicU64, Ordering};
 
-use super::{SCALE,TILE_SIZE,Rect, Player};
+use super::{Rect, GameRng, Point, BaseMap, Algorithm2D, MovementProfile, MapBuilder, SimpleMapBuilder, TileType};
+#[cfg(feature = "render")]
+use super::{SCALE, Camera};
 
-pub const MAPWIDTH : usize = 80;
-pub const MAPHEIGHT : usize = 50;
-pub const MAPCOUNT : usize = MAPHEIGHT * MAPWIDTH;
+/// Size of generated levels unless the game asks for something else
+pub const DEFAULT_MAP_WIDTH : i32 = 80;
+pub const DEFAULT_MAP_HEIGHT : i32 = 50;
 
 
-#[derive(PartialEq, Copy, Clone)]
-pub enum TileType {
-    Wall, Floor
+/// Extra cost of stepping onto a tile that's only walked through when there's no other way
+pub const AVOID_COST : f32 = 100.0;
+
+/// Extra cost of stepping through a closed door, the turn it takes to open it
+pub const DOOR_COST : f32 = 1.0;
+
+/// How bright a tile has to be for the player to make it out from further away than right next to it
+pub const LIT_THRESHOLD : f32 = 0.1;
+
+/// Visible tiles are drawn at least this bright, so the dark ones next to the player can still be made out
+#[cfg(feature = "render")]
+const MIN_SHADE : f32 = 0.25;
+
+
+/// What it takes to step onto a tile
+#[derive(PartialEq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum MoveCost {
+    /// walkable, the step costs this many times a step over bare floor
+    Cost(f32),
+    /// walkable, but only taken when every other route is blocked
+    Avoid,
+    /// never walked onto
+    Impassable,
+}
+
+impl MoveCost {
+    ///the cost of a step that would cost `base` over bare floor, None if it can't be taken
+    pub fn step(self, base: f32) -> Option<f32> {
+        match self {
+            MoveCost::Cost(multiplier) => Some(base * multiplier),
+            MoveCost::Avoid => Some(base + AVOID_COST),
+            MoveCost::Impassable => None,
+        }
+    }
 }
 
+/// What a door standing on a tile lets through
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum DoorState {
+    Open,
+    /// blocks sight, anything that can open doors gets through
+    Closed,
+    /// blocks sight, only opens for someone carrying a key
+    Locked,
+}
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Map {
     pub tiles : Vec<TileType>,
     pub rooms : Vec<Rect>,
     pub width : i32,
     pub height : i32,
+    /// how deep in the dungeon this level is, the first level is 1
+    pub depth : i32,
     pub revealed_tiles : Vec<bool>,
     pub visible_tiles : Vec<bool>,
     pub blocked : Vec<bool>,
-    pub tile_content : Vec<Vec<Entity>>
+    /// doors standing on each tile, kept up to date from the `Door` entities by `MapIndexingSystem`
+    pub doors : Vec<Option<DoorState>>,
+    /// light falling on each tile as red, green and blue, 1.0 being full brightness.
+    /// Worked out from scratch every turn by `LightingSystem`
+    #[serde(skip)]
+    pub light : Vec<[f32; 3]>,
+    #[serde(skip)]
+    pub tile_content : Vec<Vec<Entity>>,
+    /// changes whenever anything that blocks sight does, see `bump_revision`
+    #[serde(skip, default = "next_revision")]
+    revision : u64,
+}
+
+/// Revisions are handed out from one counter shared by every map, so two maps never share one
+/// and a loaded or freshly built map can't be mistaken for whatever was there before
+static REVISIONS : AtomicU64 = AtomicU64::new(1);
+
+fn next_revision() -> u64 {
+    REVISIONS.fetch_add(1, Ordering::Relaxed)
 }
 
 
@@ -35,10 +101,6 @@ impl Map {
         (y as usize * self.width as usize) + x as usize
     }
 
-    pub fn is_opaque(&self, idx:usize) -> bool {
-        self.tiles[idx as usize] == TileType::Wall
-    }
-
     pub fn in_bounds(&self,x: i32, y: i32) -> bool {
         x >= 0 && x < self.width && y >= 0 && y < self.height
     }
@@ -47,118 +109,124 @@ impl Map {
 
     pub fn populate_blocked(&mut self) {
         for (i,tile) in self.tiles.iter_mut().enumerate() {
-            self.blocked[i] = *tile == TileType::Wall;
+            self.blocked[i] = tile.def().solid;
         }
     }
 
 
-    pub fn clear_content_index(&mut self) {
-        for content in self.tile_content.iter_mut() {
-            content.clear();
-        }
+    ///what anything worked out from the map's walls and doors, like a field of view, can be cached against
+    pub fn revision(&self) -> u64 {
+        self.revision
     }
 
-    pub fn new_map_rooms_and_corridors() -> Map {
-        let mut map = Map{
-            tiles: vec![TileType::Wall;MAPCOUNT],
-            rooms: Vec::new(),
-            width: 80,
-            height: 50,
-            revealed_tiles: vec![false;MAPCOUNT],
-            visible_tiles: vec![false;MAPCOUNT],
-            blocked: vec![false;MAPCOUNT],
-            tile_content : vec![Vec::new(); MAPCOUNT]
-        };
+    ///call after changing tiles or doors once the level is in play, so cached fields of view are redone
+    pub fn bump_revision(&mut self) {
+        self.revision = next_revision();
+    }
 
-        const MAX_ROOMS : i32 = 30;
-        const MIN_SIZE : i32 = 6;
-        const MAX_SIZE : i32 = 10;
-
-        let mut rng = rand::thread_rng();
-
-        for _ in 0..MAX_ROOMS {
-            let w = rng.gen_range(MIN_SIZE..MAX_SIZE);
-            let h = rng.gen_range(MIN_SIZE..MAX_SIZE);
-            let x = rng.gen_range(1..(80 - w - 1)) - 1;
-            let y = rng.gen_range(1..(50 - h - 1)) - 1;
-            let new_room = Rect::new(x, y, w, h);
-            let mut ok = true;
-            for other_room in map.rooms.iter() {
-                if new_room.intersect(other_room) { ok = false }
-            }
-            if ok {
-                map.apply_room_to_map(&new_room);
-
-                if !map.rooms.is_empty() {
-                    let (new_x, new_y) = new_room.center();
-                    let (prev_x, prev_y) = map.rooms[map.rooms.len()-1].center();
-                    if rng.gen_range(0..2) == 1 {
-                        map.apply_horizontal_tunnel(prev_x, new_x, prev_y);
-                        map.apply_vertical_tunnel(prev_y, new_y, new_x);
-                    } else {
-                        map.apply_vertical_tunnel(prev_y, new_y, prev_x);
-                        map.apply_horizontal_tunnel( prev_x, new_x, new_y);
-                    }
-                }
-
-                map.rooms.push(new_room);         
-            }
-        }
+    ///true if a closed or locked door stands on `idx`
+    pub fn door_closed(&self, idx: usize) -> bool {
+        matches!(self.doors[idx], Some(DoorState::Closed | DoorState::Locked))
+    }
 
-        map
+    ///how bright the brightest colour of light on `idx` is
+    pub fn brightness(&self, idx: usize) -> f32 {
+        self.light.get(idx).map_or(0.0, |light| light[0].max(light[1]).max(light[2]))
     }
 
+    ///true if there's enough light on `idx` to see it from a distance
+    pub fn is_lit(&self, idx: usize) -> bool {
+        self.brightness(idx) >= LIT_THRESHOLD
+    }
 
-    
-    fn is_exit_valid(&self, x:i32, y:i32) -> bool {
-        if x < 1 || x > self.width-1 || y < 1 || y > self.height-1 { return false; }
-        let idx = self.xy_idx(x, y);
-        !self.blocked[idx]
+    ///the cost of stepping onto `idx` for anything without a `MovementProfile`
+    pub fn move_cost(&self, idx: usize) -> MoveCost {
+        self.tiles[idx].move_cost()
     }
 
-    pub fn get_available_exits(&self, idx:usize) -> Vec<(usize, f32)> {
-        let mut exits = Vec::new();
-        let x = idx as i32 % self.width;
-        let y = idx as i32 / self.width;
-        let w = self.width as usize;
+    ///a view of the map that prices every step with `profile` instead of the tiles' own costs,
+    ///hand it to `AStarPathfinder` or `DijkstraMap` to path for that entity
+    pub fn with_profile<'a>(&'a self, profile: &'a MovementProfile) -> ProfiledMap<'a> {
+        ProfiledMap { map: self, profile: Some(profile) }
+    }
 
-        // Cardinal directions
-        if self.is_exit_valid(x-1, y) { exits.push((idx-1, 1.0)) };
-        if self.is_exit_valid(x+1, y) { exits.push((idx+1, 1.0)) };
-        if self.is_exit_valid(x, y-1) { exits.push((idx-w, 1.0)) };
-        if self.is_exit_valid(x, y+1) { exits.push((idx+w, 1.0)) };
+    ///the map as something moving with `profile` sees it, or as the map itself prices steps if it has none
+    pub fn walker_for<'a>(&'a self, profile: Option<&'a MovementProfile>) -> ProfiledMap<'a> {
+        ProfiledMap { map: self, profile }
+    }
 
-        // Diagonals
-        if self.is_exit_valid(x-1, y-1) { exits.push(((idx-w)-1, 1.45)); }
-        if self.is_exit_valid(x+1, y-1) { exits.push(((idx-w)+1, 1.45)); }
-        if self.is_exit_valid(x-1, y+1) { exits.push(((idx+w)-1, 1.45)); }
-        if self.is_exit_valid(x+1, y+1) { exits.push(((idx+w)+1, 1.45)); }
+    pub fn clear_content_index(&mut self) {
+        for content in self.tile_content.iter_mut() {
+            content.clear();
+        }
+    }
 
-        exits
+    ///index of the first tile of the given type, if the map has one
+    pub fn find_tile(&self, tile: TileType) -> Option<usize> {
+        self.tiles.iter().position(|t| *t == tile)
     }
 
-    pub fn get_available_cardinal_exits(&self, idx:usize) -> Vec<(usize, f32)> {
-        let mut exits = Vec::new();
+    ///a `width` by `height` level of solid wall for a `MapBuilder` to carve into
+    pub fn new(depth: i32, width: i32, height: i32) -> Map {
+        let count = (width * height) as usize;
+        Map{
+            tiles: vec![TileType::Wall;count],
+            rooms: Vec::new(),
+            width,
+            height,
+            depth,
+            revealed_tiles: vec![false;count],
+            visible_tiles: vec![false;count],
+            blocked: vec![false;count],
+            doors: vec![None;count],
+            light: vec![[0.0; 3];count],
+            tile_content : vec![Vec::new(); count],
+            revision: next_revision(),
+        }
+    }
+
+    ///generates the level at `depth` with the classic rooms and corridors layout, see `SimpleMapBuilder`
+    pub fn new_map_rooms_and_corridors(rng: &mut GameRng, depth: i32, width: i32, height: i32) -> Map {
+        SimpleMapBuilder::new().build_map(rng, depth, Point::new(width, height)).map
+    }
+
+    ///walks the eight neighbours of `idx`, pricing each step by what `cost_of` says about the tile it lands on.
+    ///locked doors are never passed, closed ones only if `opens_doors`
+    fn for_each_exit_priced(&self, idx: usize, cost_of: &dyn Fn(TileType) -> MoveCost, opens_doors: bool, f: &mut dyn FnMut(usize, f32)) {
         let x = idx as i32 % self.width;
         let y = idx as i32 / self.width;
         let w = self.width as usize;
+        let mut exit = |valid: bool, exit: usize, base: f32| {
+            if !valid { return; }
+            let door_cost = match self.doors[exit] {
+                Some(DoorState::Locked) => return,
+                Some(DoorState::Closed) if !opens_doors => return,
+                Some(DoorState::Closed) => DOOR_COST,
+                _ => 0.0,
+            };
+            if let Some(cost) = cost_of(self.tiles[exit]).step(base) {
+                f(exit, cost + door_cost);
+            }
+        };
 
         // Cardinal directions
-        if self.is_exit_valid(x-1, y) { exits.push((idx-1, 1.0)) };
-        if self.is_exit_valid(x+1, y) { exits.push((idx+1, 1.0)) };
-        if self.is_exit_valid(x, y-1) { exits.push((idx-w, 1.0)) };
-        if self.is_exit_valid(x, y+1) { exits.push((idx+w, 1.0)) };
-        exits
-    }
+        exit(self.is_exit_valid(x-1, y), idx.wrapping_sub(1), 1.0);
+        exit(self.is_exit_valid(x+1, y), idx+1, 1.0);
+        exit(self.is_exit_valid(x, y-1), idx.wrapping_sub(w), 1.0);
+        exit(self.is_exit_valid(x, y+1), idx+w, 1.0);
 
-    pub fn get_pathing_distance(&self, idx1:usize, idx2:usize) -> f32 {
-        let w = self.width as usize;
-        let p1 = (idx1 % w, idx1 / w);
-        let p2 = (idx2 % w, idx2 / w);
-        //pythagorus distance crap
-        Self::distance2d_pythagoras(p1.0 as i32,p1.1 as i32,p2.0 as i32,p2.1 as i32)
+        // Diagonals
+        exit(self.is_exit_valid(x-1, y-1), idx.wrapping_sub(w+1), 1.45);
+        exit(self.is_exit_valid(x+1, y-1), idx.wrapping_sub(w).wrapping_add(1), 1.45);
+        exit(self.is_exit_valid(x-1, y+1), (idx+w).wrapping_sub(1), 1.45);
+        exit(self.is_exit_valid(x+1, y+1), idx+w+1, 1.45);
     }
 
+    fn is_exit_valid(&self, x:i32, y:i32) -> bool {
+        if x < 1 || x > self.width-1 || y < 1 || y > self.height-1 { return false; }
+        let idx = self.xy_idx(x, y);
+        !self.blocked[idx]
+    }
 
     pub fn distance2d_pythagoras(start_x: i32, start_y: i32, end_x: i32, end_y: i32) -> f32 {
         let dsq = Self::distance2d_pythagoras_squared(start_x,start_y,end_x, end_y);
@@ -173,7 +241,7 @@ impl Map {
 
 
 
-    fn apply_room_to_map(&mut self,room : &Rect) {
+    pub fn apply_room_to_map(&mut self,room : &Rect) {
         for y in room.y1 +1 ..= room.y2 {
             for x in room.x1 + 1 ..= room.x2 {
                 let idx = self.xy_idx(x, y);
@@ -183,57 +251,119 @@ impl Map {
     }
 
 
-    fn apply_horizontal_tunnel(&mut self, x1:i32, x2:i32, y:i32) {
+    pub fn apply_horizontal_tunnel(&mut self, x1:i32, x2:i32, y:i32) {
         for x in min(x1,x2) ..= max(x1,x2) {
-            let idx = self.xy_idx(x, y);
-            if idx > 0 && idx < MAPCOUNT {
-                self.tiles[idx as usize] = TileType::Floor;
+            if self.in_bounds(x, y) {
+                let idx = self.xy_idx(x, y);
+                self.tiles[idx] = TileType::Floor;
             }
         }
     }
 
-    fn apply_vertical_tunnel(&mut self, y1:i32, y2:i32, x:i32) {
+    pub fn apply_vertical_tunnel(&mut self, y1:i32, y2:i32, x:i32) {
         for y in min(y1,y2) ..= max(y1,y2) {
-            let idx = self.xy_idx(x, y);
-            if idx > 0 && idx < MAPCOUNT {
-                self.tiles[idx as usize] = TileType::Floor;
+            if self.in_bounds(x, y) {
+                let idx = self.xy_idx(x, y);
+                self.tiles[idx] = TileType::Floor;
             }
         }
     }
 }
 
-pub fn draw_map(ecs: &World, draw: &mut RaylibDrawHandle, tileset: &Vec<Texture2D>) {
+impl BaseMap for Map {
+    fn is_opaque(&self, idx:usize) -> bool {
+        self.tiles[idx].def().opaque || self.door_closed(idx)
+    }
+
+    fn get_available_exits(&self, idx:usize) -> Vec<(usize, f32)> {
+        let mut exits = Vec::new();
+        self.for_each_exit(idx, &mut |exit, cost| exits.push((exit, cost)));
+        exits
+    }
+
+    fn for_each_exit(&self, idx: usize, f: &mut dyn FnMut(usize, f32)) {
+        // whatever has no profile of its own moves like the player, who opens doors
+        self.for_each_exit_priced(idx, &|tile| tile.move_cost(), true, f);
+    }
+
+    fn get_pathing_distance(&self, idx1:usize, idx2:usize) -> f32 {
+        let w = self.width as usize;
+        let p1 = (idx1 % w, idx1 / w);
+        let p2 = (idx2 % w, idx2 / w);
+        //pythagorus distance crap
+        Self::distance2d_pythagoras(p1.0 as i32,p1.1 as i32,p2.0 as i32,p2.1 as i32)
+    }
+}
+
+impl Algorithm2D for Map {
+    fn dimensions(&self) -> Point {
+        Point::new(self.width, self.height)
+    }
+}
+
+/// A `Map` seen through an entity's `MovementProfile`: same walls and blockers, but every
+/// step is priced the way that entity moves. Built with `Map::with_profile`, or `Map::walker_for`
+/// for an entity that may not have a profile, in which case it moves just like the `Map`.
+pub struct ProfiledMap<'a> {
+    pub map: &'a Map,
+    pub profile: Option<&'a MovementProfile>,
+}
+
+impl BaseMap for ProfiledMap<'_> {
+    fn is_opaque(&self, idx:usize) -> bool {
+        self.map.is_opaque(idx)
+    }
+
+    fn get_available_exits(&self, idx:usize) -> Vec<(usize, f32)> {
+        let mut exits = Vec::new();
+        self.for_each_exit(idx, &mut |exit, cost| exits.push((exit, cost)));
+        exits
+    }
+
+    fn for_each_exit(&self, idx: usize, f: &mut dyn FnMut(usize, f32)) {
+        match self.profile {
+            Some(profile) => self.map.for_each_exit_priced(idx, &|tile| profile.cost(tile), profile.opens_doors, f),
+            None => self.map.for_each_exit(idx, f),
+        }
+    }
+
+    fn get_pathing_distance(&self, idx1:usize, idx2:usize) -> f32 {
+        // scaled down so A* never overestimates for movers that find some terrain cheaper than floor
+        self.map.get_pathing_distance(idx1, idx2) * self.profile.map_or(1.0, |profile| profile.cheapest_step())
+    }
+}
+
+impl Algorithm2D for ProfiledMap<'_> {
+    fn dimensions(&self) -> Point {
+        self.map.dimensions()
+    }
+}
+
+#[cfg(feature = "render")]
+pub fn draw_map(ecs: &World, draw: &mut RaylibDrawHandle, tileset: &[Texture2D]) {
     
     let map = ecs.fetch::<Map>();
-    let mut y = 0;
-    let mut x = 0;
-    for (idx,tile) in map.tiles.iter().enumerate() {
-        let sprite;
-        let mut fg = Color::WHITE;
-        // Render a tile depending upon the tile type
-        if map.revealed_tiles[idx] { 
-            match tile {
-                TileType::Floor => {
-                    //draw.draw_texture_ex(&tileset[17],Vector2::new((x * TILE_SIZE) as f32 * SCALE ,(y * TILE_SIZE) as f32 * SCALE),0.0,SCALE,Color::WHITE);
-                    sprite = &tileset[17];
-                }
-                TileType::Wall => {
-                    //draw.draw_texture_ex(&tileset[1],Vector2::new((x * TILE_SIZE) as f32 * SCALE ,(y * TILE_SIZE) as f32 * SCALE),0.0,SCALE,Color::WHITE);
-                    sprite = &tileset[1];
-                }
-            }
-            if !map.visible_tiles[idx] { fg = Color::GRAY};
-            draw.draw_texture_ex(sprite, Vector2::new((x * TILE_SIZE) as f32 * SCALE ,(y * TILE_SIZE) as f32 * SCALE), 0.0, SCALE, fg);
-            //if map.blocked[idx] {
-            //    draw.draw_pixel_v(Vector2::new((x * TILE_SIZE) as f32 * SCALE ,(y * TILE_SIZE) as f32 * SCALE), Color::RED);
-            //}
-        }
-        // Move the coordinates
-        x += 1;
-        if x > (MAPWIDTH - 1) as i32 {
-            x = 0;
-            y += 1;
+    let camera = ecs.fetch::<Camera>();
+    // only the tiles in view
+    for y in camera.y.max(0) .. (camera.y + camera.height).min(map.height) {
+        for x in camera.x.max(0) .. (camera.x + camera.width).min(map.width) {
+            let idx = map.xy_idx(x, y);
+            if !map.revealed_tiles[idx] { continue; }
+            let tile = map.tiles[idx].def();
+            // tiles in sight take on the colour of the light on them, remembered ones are drawn at half brightness
+            let shade = if map.visible_tiles[idx] {
+                map.light.get(idx).map_or([1.0; 3], |light| light.map(|c| MIN_SHADE + (1.0 - MIN_SHADE) * c.min(1.0)))
+            } else {
+                [0.5; 3]
+            };
+            let fg = Color::new(
+                (tile.tint.r as f32 * shade[0]) as u8,
+                (tile.tint.g as f32 * shade[1]) as u8,
+                (tile.tint.b as f32 * shade[2]) as u8,
+                tile.tint.a,
+            );
+            let (screen_x, screen_y) = camera.map_to_screen(x, y);
+            draw.draw_texture_ex(&tileset[tile.sprite as usize], Vector2::new(screen_x, screen_y), 0.0, SCALE, fg);
         }
     }
 }
-
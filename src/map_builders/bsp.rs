@@ -0,0 +1,104 @@
+use rand::Rng;
+
+use super::{MapBuilder, BuiltLevel, finish_room_level};
+use crate::{Map, Point, Rect, GameRng, TileType};
+
+
+/// Binary space partition: keeps splitting the map into smaller and smaller areas and puts a
+/// room in some of them, so rooms never overlap and fill the map more evenly than random placement.
+/// Rooms are joined left to right by corridors.
+pub struct BspMapBuilder {
+    /// how many times to try placing a room before giving up
+    pub attempts: usize,
+    rects: Vec<Rect>,
+}
+
+impl Default for BspMapBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl BspMapBuilder {
+    pub fn new() -> BspMapBuilder {
+        BspMapBuilder { attempts: 240, rects: Vec::new() }
+    }
+
+    ///splits `rect` into quarters
+    fn add_subrects(&mut self, rect: &Rect) {
+        let width = i32::abs(rect.x1 - rect.x2);
+        let height = i32::abs(rect.y1 - rect.y2);
+        let half_width = i32::max(width / 2, 1);
+        let half_height = i32::max(height / 2, 1);
+
+        self.rects.push(Rect::new(rect.x1, rect.y1, half_width, half_height));
+        self.rects.push(Rect::new(rect.x1, rect.y1 + half_height, half_width, half_height));
+        self.rects.push(Rect::new(rect.x1 + half_width, rect.y1, half_width, half_height));
+        self.rects.push(Rect::new(rect.x1 + half_width, rect.y1 + half_height, half_width, half_height));
+    }
+
+    ///a room somewhere inside `rect`, at most 10 tiles a side
+    fn random_sub_rect(rect: &Rect, rng: &mut GameRng) -> Rect {
+        let rect_width = i32::abs(rect.x1 - rect.x2);
+        let rect_height = i32::abs(rect.y1 - rect.y2);
+
+        let w = i32::max(3, rng.gen_range(1..=i32::max(1, i32::min(rect_width, 10)))) + 1;
+        let h = i32::max(3, rng.gen_range(1..=i32::max(1, i32::min(rect_height, 10)))) + 1;
+        let x = rect.x1 + rng.gen_range(0..=i32::max(0, rect_width - 1));
+        let y = rect.y1 + rng.gen_range(0..=i32::max(0, rect_height - 1));
+        Rect::new(x, y, w, h)
+    }
+
+    ///true if `rect` plus a one tile border sits on solid wall inside the map
+    fn is_possible(map: &Map, rect: &Rect) -> bool {
+        for y in rect.y1 - 1 ..= rect.y2 + 1 {
+            for x in rect.x1 - 1 ..= rect.x2 + 1 {
+                if x < 1 || x > map.width - 2 || y < 1 || y > map.height - 2 { return false; }
+                if map.tiles[map.xy_idx(x, y)] != TileType::Wall { return false; }
+            }
+        }
+        true
+    }
+
+    ///digs from one point to another, first along x then along y
+    fn draw_corridor(map: &mut Map, x1: i32, y1: i32, x2: i32, y2: i32) {
+        map.apply_horizontal_tunnel(x1, x2, y1);
+        map.apply_vertical_tunnel(y1, y2, x2);
+    }
+}
+
+impl MapBuilder for BspMapBuilder {
+    fn build_map(&mut self, rng: &mut GameRng, depth: i32, size: Point) -> BuiltLevel {
+        let mut map = Map::new(depth, size.x, size.y);
+
+        self.rects.clear();
+        let whole = Rect::new(2, 2, map.width - 5, map.height - 5);
+        self.rects.push(whole.clone());
+        self.add_subrects(&whole);
+
+        for _ in 0..self.attempts {
+            let rect = self.rects[rng.gen_range(0..self.rects.len())].clone();
+            let candidate = Self::random_sub_rect(&rect, rng);
+            if Self::is_possible(&map, &candidate) {
+                map.apply_room_to_map(&candidate);
+                map.rooms.push(candidate);
+                self.add_subrects(&rect);
+            }
+        }
+        if map.rooms.is_empty() {
+            // the map is too small to split, one room will have to do
+            let room = Rect::new(1, 1, map.width - 3, map.height - 3);
+            map.apply_room_to_map(&room);
+            map.rooms.push(room);
+        }
+
+        map.rooms.sort_by_key(|room| room.x1);
+        for i in 1..map.rooms.len() {
+            let (start_x, start_y) = map.rooms[i-1].center();
+            let (end_x, end_y) = map.rooms[i].center();
+            Self::draw_corridor(&mut map, start_x, start_y, end_x, end_y);
+        }
+
+        finish_room_level(map, rng)
+    }
+}
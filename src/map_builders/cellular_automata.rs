@@ -0,0 +1,77 @@
+use rand::Rng;
+
+use super::{MapBuilder, BuiltLevel, finish_open_level, central_floor};
+use crate::{Map, Point, GameRng, TileType};
+
+
+/// Natural looking caves: starts from noise and repeatedly smooths it, a tile turns into whatever
+/// most of its neighbours are. Pockets the player can't reach are filled in afterwards.
+pub struct CellularAutomataBuilder {
+    /// chance out of 100 that a tile starts as floor
+    pub floor_chance: i32,
+    pub iterations: usize,
+}
+
+impl Default for CellularAutomataBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl CellularAutomataBuilder {
+    pub fn new() -> CellularAutomataBuilder {
+        CellularAutomataBuilder { floor_chance: 55, iterations: 15 }
+    }
+
+    fn smooth(map: &mut Map) {
+        let mut next = map.tiles.clone();
+        for y in 1..map.height - 1 {
+            for x in 1..map.width - 1 {
+                let mut walls = 0;
+                for (dx, dy) in [(-1, -1), (0, -1), (1, -1), (-1, 0), (1, 0), (-1, 1), (0, 1), (1, 1)] {
+                    if map.tiles[map.xy_idx(x + dx, y + dy)] == TileType::Wall { walls += 1; }
+                }
+                let idx = map.xy_idx(x, y);
+                if walls > 4 {
+                    next[idx] = TileType::Wall;
+                } else if walls < 4 {
+                    next[idx] = TileType::Floor;
+                }
+            }
+        }
+        map.tiles = next;
+    }
+}
+
+impl MapBuilder for CellularAutomataBuilder {
+    fn build_map(&mut self, rng: &mut GameRng, depth: i32, size: Point) -> BuiltLevel {
+        const MAX_TRIES : usize = 10;
+
+        let mut best : Option<(usize, BuiltLevel)> = None;
+        for _ in 0..MAX_TRIES {
+            let mut map = Map::new(depth, size.x, size.y);
+            for y in 1..map.height - 1 {
+                for x in 1..map.width - 1 {
+                    if rng.gen_range(0..100) < self.floor_chance {
+                        let idx = map.xy_idx(x, y);
+                        map.tiles[idx] = TileType::Floor;
+                    }
+                }
+            }
+            for _ in 0..self.iterations {
+                Self::smooth(&mut map);
+            }
+
+            let start = central_floor(&map);
+            let built = finish_open_level(map, start, rng);
+            // the middle landed in a pocket, try again rather than hand out a closet
+            let open = built.map.tiles.iter().filter(|t| **t != TileType::Wall).count();
+            let big_enough = open >= built.map.tiles.len() / 4;
+            if best.as_ref().is_none_or(|(best_open, _)| open > *best_open) {
+                best = Some((open, built));
+            }
+            if big_enough { break; }
+        }
+        best.expect("at least one cave is generated").1
+    }
+}
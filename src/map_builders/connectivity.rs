@@ -0,0 +1,181 @@
+use std::collections::VecDeque;
+use std::fmt;
+
+use super::BuiltLevel;
+use crate::{Map, Point, TileType};
+
+
+/// What a connectivity check found on a level. Walkability only looks at the tiles,
+/// anything standing on them is ignored, and steps may be diagonal just like when pathing.
+#[derive(Debug, Default)]
+pub struct ConnectivityReport {
+    /// the start tile itself can't be stood on
+    pub start_blocked: bool,
+    /// walkable tiles reachable from the start
+    pub reachable: usize,
+    /// groups of walkable tiles that can't be reached from the start, each a list of tile indices
+    pub unreachable_regions: Vec<Vec<usize>>,
+    /// stairs that can't be reached from the start
+    pub unreachable_stairs: Vec<Point>,
+    /// spawn points that are walled in or on a tile that can't be stood on
+    pub unreachable_spawns: Vec<Point>,
+}
+
+impl ConnectivityReport {
+    ///true if everything walkable, every stair and every spawn point can be reached from the start
+    pub fn is_connected(&self) -> bool {
+        !self.start_blocked
+            && self.unreachable_regions.is_empty()
+            && self.unreachable_stairs.is_empty()
+            && self.unreachable_spawns.is_empty()
+    }
+}
+
+impl fmt::Display for ConnectivityReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.start_blocked {
+            return write!(f, "the start can't be stood on");
+        }
+        write!(f, "{} tiles reachable, {} unreachable regions", self.reachable, self.unreachable_regions.len())?;
+        for stairs in self.unreachable_stairs.iter() {
+            write!(f, ", stairs at ({}, {}) unreachable", stairs.x, stairs.y)?;
+        }
+        for spawn in self.unreachable_spawns.iter() {
+            write!(f, ", spawn at ({}, {}) unreachable", spawn.x, spawn.y)?;
+        }
+        Ok(())
+    }
+}
+
+
+fn walkable(map: &Map, idx: usize) -> bool {
+    map.tiles[idx].walkable()
+}
+
+fn is_stairs(tile: TileType) -> bool {
+    tile == TileType::DownStairs || tile == TileType::UpStairs
+}
+
+///flood fills the walkable tiles connected to `from`, calling `f` with each
+fn flood(map: &Map, from: usize, seen: &mut [bool], f: &mut dyn FnMut(usize)) {
+    let mut open = VecDeque::new();
+    seen[from] = true;
+    open.push_back(from);
+    while let Some(idx) = open.pop_front() {
+        f(idx);
+        let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+        for dy in -1 ..= 1 {
+            for dx in -1 ..= 1 {
+                if !map.in_bounds(x + dx, y + dy) { continue; }
+                let next = map.xy_idx(x + dx, y + dy);
+                if !seen[next] && walkable(map, next) {
+                    seen[next] = true;
+                    open.push_back(next);
+                }
+            }
+        }
+    }
+}
+
+///checks that everything on `level` can be reached from its start, without changing anything
+pub fn check_connectivity(level: &BuiltLevel) -> ConnectivityReport {
+    let map = &level.map;
+    let mut report = ConnectivityReport::default();
+    let to_point = |idx: usize| Point::new(idx as i32 % map.width, idx as i32 / map.width);
+
+    let mut reached = vec![false; map.tiles.len()];
+    let start = map.xy_idx(level.start.x, level.start.y);
+    if walkable(map, start) {
+        flood(map, start, &mut reached, &mut |_| report.reachable += 1);
+    } else {
+        report.start_blocked = true;
+    }
+
+    let mut seen = reached.clone();
+    for idx in 0..map.tiles.len() {
+        if seen[idx] || !walkable(map, idx) { continue; }
+        let mut region = Vec::new();
+        flood(map, idx, &mut seen, &mut |tile| region.push(tile));
+        report.unreachable_stairs.extend(region.iter().filter(|tile| is_stairs(map.tiles[**tile])).map(|tile| to_point(*tile)));
+        report.unreachable_regions.push(region);
+    }
+
+    report.unreachable_spawns = level.spawn_points.iter()
+        .filter(|p| !map.in_bounds(p.x, p.y) || !reached[map.xy_idx(p.x, p.y)])
+        .copied()
+        .collect();
+    report
+}
+
+///makes everything on `level` reachable from its start: the start tile becomes floor if it has to,
+///regions holding stairs, spawn points or doors get a tunnel dug to the reachable part of the map, every other
+///unreachable region is filled in, and spawn points that still can't be reached are dropped.
+///returns what was wrong before the repair
+pub fn repair_connectivity(level: &mut BuiltLevel) -> ConnectivityReport {
+    let report = check_connectivity(level);
+    if report.is_connected() {
+        return report;
+    }
+
+    let start = level.map.xy_idx(level.start.x, level.start.y);
+    if report.start_blocked {
+        level.map.tiles[start] = TileType::Floor;
+    }
+
+    let mut reached = vec![false; level.map.tiles.len()];
+    flood(&level.map, start, &mut reached, &mut |_| {});
+
+    for region in check_connectivity(level).unreachable_regions {
+        // an earlier tunnel already ran into it
+        if region.iter().any(|idx| reached[*idx]) { continue; }
+        let map = &mut level.map;
+        let important = region.iter().copied().find(|idx| {
+            let point = Point::new(*idx as i32 % map.width, *idx as i32 / map.width);
+            is_stairs(map.tiles[*idx]) || level.spawn_points.contains(&point) || level.doors.iter().any(|(door, _)| *door == point)
+        });
+        match important {
+            Some(from) => {
+                dig_to_nearest(map, from, &reached);
+                // the region and whatever the tunnel ran into are reachable now
+                flood(map, from, &mut reached, &mut |_| {});
+            }
+            None => {
+                for idx in region {
+                    map.tiles[idx] = TileType::Wall;
+                }
+            }
+        }
+    }
+
+    let map = &level.map;
+    level.spawn_points.retain(|p| map.in_bounds(p.x, p.y) && reached[map.xy_idx(p.x, p.y)] && walkable(map, map.xy_idx(p.x, p.y)));
+    report
+}
+
+///digs an L-shaped tunnel from `from` to the closest tile marked in `reached`
+fn dig_to_nearest(map: &mut Map, from: usize, reached: &[bool]) {
+    let (fx, fy) = (from as i32 % map.width, from as i32 / map.width);
+    let nearest = (0..map.tiles.len())
+        .filter(|idx| reached[*idx])
+        .min_by_key(|idx| {
+            let (x, y) = (*idx as i32 % map.width, *idx as i32 / map.width);
+            (x - fx) * (x - fx) + (y - fy) * (y - fy)
+        });
+    if let Some(to) = nearest {
+        let (tx, ty) = (to as i32 % map.width, to as i32 / map.width);
+        for x in fx.min(tx) ..= fx.max(tx) {
+            dig(map, x, fy);
+        }
+        for y in fy.min(ty) ..= fy.max(ty) {
+            dig(map, tx, y);
+        }
+    }
+}
+
+///turns the tile into floor if it can't be walked on, leaving stairs and rough terrain alone
+fn dig(map: &mut Map, x: i32, y: i32) {
+    let idx = map.xy_idx(x, y);
+    if !walkable(map, idx) {
+        map.tiles[idx] = TileType::Floor;
+    }
+}
@@ -0,0 +1,69 @@
+use rand::Rng;
+
+use super::{MapBuilder, BuiltLevel, finish_open_level};
+use crate::{Map, Point, GameRng, TileType};
+
+
+/// Drunkard's walk: diggers stumble around at random from the middle of the map until enough
+/// of it is floor. Everything dug is connected, the result looks like eroded tunnels.
+pub struct DrunkardsWalkBuilder {
+    /// steps each digger takes before it sobers up
+    pub lifetime: usize,
+    /// share of the map to dig out, in percent
+    pub floor_percent: usize,
+}
+
+impl Default for DrunkardsWalkBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl DrunkardsWalkBuilder {
+    pub fn new() -> DrunkardsWalkBuilder {
+        DrunkardsWalkBuilder { lifetime: 400, floor_percent: 50 }
+    }
+}
+
+impl MapBuilder for DrunkardsWalkBuilder {
+    fn build_map(&mut self, rng: &mut GameRng, depth: i32, size: Point) -> BuiltLevel {
+        const MAX_DIGGERS : usize = 1000;
+
+        let mut map = Map::new(depth, size.x, size.y);
+        let start = Point::new(map.width / 2, map.height / 2);
+        let start_idx = map.xy_idx(start.x, start.y);
+        map.tiles[start_idx] = TileType::Floor;
+
+        let wanted = map.tiles.len() * self.floor_percent / 100;
+        let mut floor = 1;
+        for digger in 0..MAX_DIGGERS {
+            if floor >= wanted { break; }
+            // the first digger sets off from the start, later ones from anywhere already dug
+            let (mut x, mut y) = if digger == 0 {
+                (start.x, start.y)
+            } else {
+                loop {
+                    let x = rng.gen_range(1..map.width - 1);
+                    let y = rng.gen_range(1..map.height - 1);
+                    if map.tiles[map.xy_idx(x, y)] == TileType::Floor { break (x, y); }
+                }
+            };
+
+            for _ in 0..self.lifetime {
+                let idx = map.xy_idx(x, y);
+                if map.tiles[idx] == TileType::Wall {
+                    map.tiles[idx] = TileType::Floor;
+                    floor += 1;
+                }
+                match rng.gen_range(0..4) {
+                    0 => if x > 1 { x -= 1 },
+                    1 => if x < map.width - 2 { x += 1 },
+                    2 => if y > 1 { y -= 1 },
+                    _ => if y < map.height - 2 { y += 1 },
+                }
+            }
+        }
+
+        finish_open_level(map, start, rng)
+    }
+}
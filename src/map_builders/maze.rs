@@ -0,0 +1,60 @@
+use rand::Rng;
+
+use super::{MapBuilder, BuiltLevel, finish_open_level};
+use crate::{Map, Point, GameRng, TileType};
+
+
+/// A perfect maze dug with a recursive backtracker: exactly one way between any two spots, lots of dead ends.
+/// Cells sit on odd coordinates, the walls between them on even ones.
+pub struct MazeBuilder {}
+
+impl Default for MazeBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MazeBuilder {
+    pub fn new() -> MazeBuilder {
+        MazeBuilder {}
+    }
+}
+
+impl MapBuilder for MazeBuilder {
+    fn build_map(&mut self, rng: &mut GameRng, depth: i32, size: Point) -> BuiltLevel {
+        let mut map = Map::new(depth, size.x, size.y);
+        let cells_x = (map.width - 1) / 2;
+        let cells_y = (map.height - 1) / 2;
+        let cell_idx = |cx: i32, cy: i32| (cy * cells_x + cx) as usize;
+        let mut visited = vec![false; (cells_x * cells_y) as usize];
+
+        // an explicit stack instead of recursion, a big maze would blow the real one
+        let mut stack = vec![(0, 0)];
+        visited[0] = true;
+        let first = map.xy_idx(1, 1);
+        map.tiles[first] = TileType::Floor;
+
+        while let Some(&(cx, cy)) = stack.last() {
+            let neighbours : Vec<(i32, i32)> = [(0, -1), (1, 0), (0, 1), (-1, 0)].iter()
+                .map(|(dx, dy)| (cx + dx, cy + dy))
+                .filter(|(nx, ny)| *nx >= 0 && *nx < cells_x && *ny >= 0 && *ny < cells_y)
+                .filter(|(nx, ny)| !visited[cell_idx(*nx, *ny)])
+                .collect();
+            if neighbours.is_empty() {
+                stack.pop();
+                continue;
+            }
+
+            let (nx, ny) = neighbours[rng.gen_range(0..neighbours.len())];
+            visited[cell_idx(nx, ny)] = true;
+            // knock down the wall between the two cells and open the new one
+            let wall = map.xy_idx(1 + cx + nx, 1 + cy + ny);
+            let cell = map.xy_idx(1 + nx * 2, 1 + ny * 2);
+            map.tiles[wall] = TileType::Floor;
+            map.tiles[cell] = TileType::Floor;
+            stack.push((nx, ny));
+        }
+
+        finish_open_level(map, Point::new(1, 1), rng)
+    }
+}
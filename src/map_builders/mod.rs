@@ -0,0 +1,231 @@
+use std::fmt;
+use std::str::FromStr;
+
+use rand::Rng;
+use serde::{Serialize, Deserialize};
+
+use super::{Map, TileType, Point, GameRng, DijkstraMap};
+
+mod simple_map;
+mod bsp;
+mod cellular_automata;
+mod drunkard;
+mod maze;
+mod connectivity;
+mod prefab;
+
+pub use simple_map::*;
+pub use bsp::*;
+pub use cellular_automata::*;
+pub use drunkard::*;
+pub use maze::*;
+pub use connectivity::*;
+pub use prefab::*;
+
+
+/// A freshly generated level: the map, where the player arrives and where monsters may be put.
+pub struct BuiltLevel {
+    pub map: Map,
+    pub start: Point,
+    pub spawn_points: Vec<Point>,
+    /// where prefabs asked for a door, and whether it's locked
+    pub doors: Vec<(Point, bool)>,
+}
+
+impl BuiltLevel {
+    ///for maps made by hand: the player starts in the centre of the first room, monsters go in the centre of the others
+    pub fn from_rooms(map: Map) -> BuiltLevel {
+        let (x, y) = map.rooms[0].center();
+        let spawn_points = map.rooms.iter().skip(1)
+            .map(|room| { let (x, y) = room.center(); Point::new(x, y) })
+            .collect();
+        BuiltLevel { map, start: Point::new(x, y), spawn_points, doors: Vec::new() }
+    }
+}
+
+
+/// Generates levels. Every call to `build_map` makes a new level from scratch, so one builder
+/// can be kept around and reused for as many levels as needed.
+pub trait MapBuilder {
+    ///generates the level at `depth`, `size.x` tiles wide and `size.y` high, including its stairs
+    fn build_map(&mut self, rng: &mut GameRng, depth: i32, size: Point) -> BuiltLevel;
+}
+
+
+/// The layouts the engine knows how to generate, for picking a builder at runtime.
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum MapStyle {
+    RoomsAndCorridors, Bsp, Caves, DrunkardsWalk, Maze
+}
+
+impl MapStyle {
+    pub const ALL : [MapStyle; 5] = [MapStyle::RoomsAndCorridors, MapStyle::Bsp, MapStyle::Caves, MapStyle::DrunkardsWalk, MapStyle::Maze];
+
+    pub fn builder(self) -> Box<dyn MapBuilder> {
+        match self {
+            MapStyle::RoomsAndCorridors => Box::new(SimpleMapBuilder::new()),
+            MapStyle::Bsp => Box::new(BspMapBuilder::new()),
+            MapStyle::Caves => Box::new(CellularAutomataBuilder::new()),
+            MapStyle::DrunkardsWalk => Box::new(DrunkardsWalkBuilder::new()),
+            MapStyle::Maze => Box::new(MazeBuilder::new()),
+        }
+    }
+
+    ///any style, the top level always gets rooms and corridors so a new game starts somewhere familiar
+    pub fn random(rng: &mut GameRng, depth: i32) -> MapStyle {
+        if depth <= 1 { return MapStyle::RoomsAndCorridors; }
+        MapStyle::ALL[rng.gen_range(0..MapStyle::ALL.len())]
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            MapStyle::RoomsAndCorridors => "rooms",
+            MapStyle::Bsp => "bsp",
+            MapStyle::Caves => "caves",
+            MapStyle::DrunkardsWalk => "drunkard",
+            MapStyle::Maze => "maze",
+        }
+    }
+}
+
+impl fmt::Display for MapStyle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for MapStyle {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        MapStyle::ALL.iter()
+            .find(|style| style.name() == s)
+            .copied()
+            .ok_or_else(|| {
+                let names : Vec<&str> = MapStyle::ALL.iter().map(|style| style.name()).collect();
+                format!("unknown map style {}, expected one of {}", s, names.join(", "))
+            })
+    }
+}
+
+
+///finishes a level carved out of rooms: stairs down in the last room, up in the first (below the top level),
+///some rooms flooded with rough terrain, the player in the first room and a monster in each of the others.
+///anything the corridors missed is connected or filled in
+fn finish_room_level(mut map: Map, rng: &mut GameRng) -> BuiltLevel {
+    apply_terrain_patches(&mut map, rng);
+
+    let (stairs_x, stairs_y) = if map.rooms.len() > 1 {
+        map.rooms[map.rooms.len()-1].center()
+    } else {
+        // a map with room for just one room, keep the stairs apart in its corner
+        (map.rooms[0].x2, map.rooms[0].y2)
+    };
+    let stairs_idx = map.xy_idx(stairs_x, stairs_y);
+    map.tiles[stairs_idx] = TileType::DownStairs;
+    if map.depth > 1 {
+        let (up_x, up_y) = map.rooms[0].center();
+        let up_idx = map.xy_idx(up_x, up_y);
+        map.tiles[up_idx] = TileType::UpStairs;
+    }
+    let mut level = BuiltLevel::from_rooms(map);
+    repair_connectivity(&mut level);
+    level
+}
+
+///floods a patch of some rooms with mud, water, rubble or grass, further down lava and chasms as well.
+///The first room is left alone, the player starts there
+fn apply_terrain_patches(map: &mut Map, rng: &mut GameRng) {
+    const TERRAIN : [TileType; 6] = [TileType::Mud, TileType::Water, TileType::Rubble, TileType::Grass, TileType::Lava, TileType::Chasm];
+    /// lava and chasms, the last two, only show up from this depth on
+    const DEEP_TERRAIN_DEPTH : i32 = 3;
+
+    let kinds = if map.depth >= DEEP_TERRAIN_DEPTH { TERRAIN.len() } else { TERRAIN.len() - 2 };
+    for i in 1..map.rooms.len() {
+        if rng.gen_range(0..3) != 0 { continue; }
+        let room = map.rooms[i].clone();
+        let terrain = TERRAIN[rng.gen_range(0..kinds)];
+        let w = rng.gen_range(2..=room.x2 - room.x1);
+        let h = rng.gen_range(2..=room.y2 - room.y1);
+        let x = rng.gen_range(room.x1 + 1..=room.x2 - w + 1);
+        let y = rng.gen_range(room.y1 + 1..=room.y2 - h + 1);
+        for ty in y..y + h {
+            for tx in x..x + w {
+                let idx = map.xy_idx(tx, ty);
+                if map.tiles[idx] == TileType::Floor {
+                    map.tiles[idx] = terrain;
+                }
+            }
+        }
+    }
+}
+
+///finishes a level without rooms (caves, mazes): floor the player can't reach from `start` is filled in,
+///the stairs down go as far from the start as possible and monsters are scattered over the floor
+fn finish_open_level(map: Map, start: Point, rng: &mut GameRng) -> BuiltLevel {
+    let mut level = BuiltLevel { map, start, spawn_points: Vec::new(), doors: Vec::new() };
+    repair_connectivity(&mut level);
+    let mut map = level.map;
+
+    let start_idx = map.xy_idx(start.x, start.y);
+    map.populate_blocked();
+    let distances = DijkstraMap::new(&[start_idx], &map, f32::MAX);
+    let mut furthest = start_idx;
+    for idx in 0..map.tiles.len() {
+        if distances.is_reachable(idx) && distances.map[idx] > distances.map[furthest] {
+            furthest = idx;
+        }
+    }
+    if furthest == start_idx {
+        // nowhere else to go, make some room for the stairs
+        furthest = map.xy_idx((start.x + 1).min(map.width - 2), start.y);
+        if furthest == start_idx {
+            furthest = map.xy_idx((start.x - 1).max(1), start.y);
+        }
+    }
+    map.populate_blocked();
+
+    map.tiles[furthest] = TileType::DownStairs;
+    if map.depth > 1 {
+        map.tiles[start_idx] = TileType::UpStairs;
+    }
+
+    let spawn_points = scatter_spawn_points(&map, start, rng);
+    BuiltLevel { map, start, spawn_points, doors: Vec::new() }
+}
+
+///roughly one monster per hundred floor tiles, none within a few steps of the start
+fn scatter_spawn_points(map: &Map, start: Point, rng: &mut GameRng) -> Vec<Point> {
+    const TILES_PER_MONSTER : usize = 100;
+    const MIN_START_DISTANCE : f32 = 8.0;
+
+    let floor : Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| map.tiles[*idx] == TileType::Floor)
+        .filter(|idx| {
+            let (x, y) = (*idx as i32 % map.width, *idx as i32 / map.width);
+            Map::distance2d_pythagoras(x, y, start.x, start.y) >= MIN_START_DISTANCE
+        })
+        .collect();
+    let wanted = (floor.len() / TILES_PER_MONSTER).max(1);
+
+    let mut spawn_points : Vec<Point> = Vec::new();
+    for _ in 0..wanted * 4 {
+        if spawn_points.len() >= wanted || floor.is_empty() { break; }
+        let idx = floor[rng.gen_range(0..floor.len())];
+        let point = Point::new(idx as i32 % map.width, idx as i32 / map.width);
+        if !spawn_points.contains(&point) {
+            spawn_points.push(point);
+        }
+    }
+    spawn_points
+}
+
+///the floor tile nearest the middle of the map, where open levels put the player
+fn central_floor(map: &Map) -> Point {
+    let (cx, cy) = (map.width / 2, map.height / 2);
+    (0..map.tiles.len())
+        .filter(|idx| map.tiles[*idx] == TileType::Floor)
+        .map(|idx| Point::new(idx as i32 % map.width, idx as i32 / map.width))
+        .min_by_key(|p| (p.x - cx) * (p.x - cx) + (p.y - cy) * (p.y - cy))
+        .unwrap_or(Point::new(cx, cy))
+}
@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use rand::Rng;
+
+use super::{BuiltLevel, repair_connectivity};
+use crate::{Map, Rect, Point, TileType, MoveCost, GameRng};
+
+
+/// Where the game looks for prefab files at startup.
+pub const PREFAB_DIR : &str = "prefabs";
+
+
+#[derive(Debug)]
+pub enum PrefabError {
+    Io(std::io::Error),
+    Parse { name: String, line: usize, message: String },
+}
+
+impl fmt::Display for PrefabError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PrefabError::Io(e) => write!(f, "could not read prefab: {}", e),
+            PrefabError::Parse { name, line, message } => write!(f, "{} line {}: {}", name, line, message),
+        }
+    }
+}
+
+impl std::error::Error for PrefabError {}
+
+impl From<std::io::Error> for PrefabError {
+    fn from(e: std::io::Error) -> Self { PrefabError::Io(e) }
+}
+
+
+/// A hand drawn room stamped into generated levels. Written as a text file: `key: value` lines
+/// (`name`, `weight`, `min_depth`, `max_depth`, `rotate`, `mirror`), a `---` line, then the room itself,
+/// every row as wide as the first, padded out with spaces where need be:
+///
+/// ```text
+/// #  wall        .  floor       +  door        =  locked door
+/// ~  water       %  mud         :  rubble
+/// M  floor with a monster on it
+///    (space) leave whatever the level has there
+/// ```
+#[derive(Clone, Debug)]
+pub struct Prefab {
+    pub name: String,
+    /// how often it's picked compared to the other prefabs, 0 never
+    pub weight: u32,
+    pub min_depth: i32,
+    pub max_depth: i32,
+    /// may be turned by 90 degrees when stamped
+    pub rotate: bool,
+    /// may be flipped left to right when stamped
+    pub mirror: bool,
+    pub width: i32,
+    pub height: i32,
+    /// `height` rows of `width` symbols each
+    pub cells: Vec<Vec<char>>,
+}
+
+impl Prefab {
+    ///reads a prefab from its text, `name` is only used in error messages
+    pub fn parse(name: &str, text: &str) -> Result<Prefab, PrefabError> {
+        let error = |line: usize, message: String| PrefabError::Parse { name: name.to_string(), line: line + 1, message };
+
+        let mut prefab = Prefab {
+            name: name.to_string(), weight: 1, min_depth: 1, max_depth: i32::MAX,
+            rotate: false, mirror: false, width: 0, height: 0, cells: Vec::new(),
+        };
+
+        let mut lines = text.lines().enumerate();
+        for (n, line) in lines.by_ref() {
+            let line = line.trim();
+            if line == "---" { break; }
+            if line.is_empty() { continue; }
+            let (key, value) = line.split_once(':').ok_or_else(|| error(n, format!("expected `key: value` or `---`, got `{}`", line)))?;
+            let value = value.trim();
+            let number = || value.parse::<i32>().map_err(|_| error(n, format!("{} needs a whole number", key.trim())));
+            let flag = || match value {
+                "yes" | "true" => Ok(true),
+                "no" | "false" => Ok(false),
+                _ => Err(error(n, format!("{} needs yes or no", key.trim()))),
+            };
+            match key.trim() {
+                "name" => prefab.name = value.to_string(),
+                "weight" => prefab.weight = number()?.max(0) as u32,
+                "min_depth" => prefab.min_depth = number()?,
+                "max_depth" => prefab.max_depth = number()?,
+                "rotate" => prefab.rotate = flag()?,
+                "mirror" => prefab.mirror = flag()?,
+                other => return Err(error(n, format!("unknown key {}", other))),
+            }
+        }
+
+        let mut rows : Vec<(usize, Vec<char>)> = Vec::new();
+        for (n, line) in lines {
+            let row : Vec<char> = line.chars().collect();
+            if let Some(bad) = row.iter().find(|c| !"#.+=~%:M ".contains(**c)) {
+                return Err(error(n, format!("unknown symbol `{}`", bad)));
+            }
+            rows.push((n, row));
+        }
+        while rows.last().is_some_and(|(_, row)| row.iter().all(|c| *c == ' ')) {
+            rows.pop();
+        }
+        let Some((_, first)) = rows.first() else {
+            return Err(error(0, "no room drawn after `---`".to_string()));
+        };
+
+        // a short row is nearly always a missing wall, which would leave a hole in the room rather than an error
+        let width = first.len();
+        if let Some((n, row)) = rows.iter().find(|(_, row)| row.len() != width) {
+            return Err(error(*n, format!("row is {} wide but the first row is {}, pad it with spaces", row.len(), width)));
+        }
+        prefab.width = width as i32;
+        prefab.height = rows.len() as i32;
+        prefab.cells = rows.into_iter().map(|(_, row)| row).collect();
+        Ok(prefab)
+    }
+
+    ///turned a quarter clockwise
+    pub fn rotated(&self) -> Prefab {
+        let cells = (0..self.width as usize)
+            .map(|x| (0..self.height as usize).rev().map(|y| self.cells[y][x]).collect())
+            .collect();
+        Prefab { width: self.height, height: self.width, cells, ..self.clone() }
+    }
+
+    ///flipped left to right
+    pub fn mirrored(&self) -> Prefab {
+        let cells = self.cells.iter().map(|row| row.iter().rev().copied().collect()).collect();
+        Prefab { cells, ..self.clone() }
+    }
+
+    ///turned and flipped at random, as far as the prefab allows
+    fn random_transform(&self, rng: &mut GameRng) -> Prefab {
+        let mut result = self.clone();
+        if self.rotate {
+            for _ in 0..rng.gen_range(0..4) {
+                result = result.rotated();
+            }
+        }
+        if self.mirror && rng.gen_range(0..2) == 1 {
+            result = result.mirrored();
+        }
+        result
+    }
+
+    ///true if the prefab can go with its top left corner on (x, y): everything it covers, plus a tile
+    ///around it, is solid wall, so it doesn't cut through anything the generator made
+    fn fits(&self, map: &Map, x: i32, y: i32) -> bool {
+        if x < 1 || y < 1 || x + self.width >= map.width || y + self.height >= map.height {
+            return false;
+        }
+        for ty in y - 1 ..= y + self.height {
+            for tx in x - 1 ..= x + self.width {
+                if map.tiles[map.xy_idx(tx, ty)] != TileType::Wall { return false; }
+            }
+        }
+        true
+    }
+
+    ///draws the prefab onto the level with its top left corner on (x, y)
+    fn stamp(&self, level: &mut BuiltLevel, x: i32, y: i32) {
+        for (dy, row) in self.cells.iter().enumerate() {
+            for (dx, symbol) in row.iter().enumerate() {
+                let (tx, ty) = (x + dx as i32, y + dy as i32);
+                let idx = level.map.xy_idx(tx, ty);
+                let tile = match symbol {
+                    '#' => TileType::Wall,
+                    '~' => TileType::Water,
+                    '%' => TileType::Mud,
+                    ':' => TileType::Rubble,
+                    ' ' => continue,
+                    _ => TileType::Floor,
+                };
+                level.map.tiles[idx] = tile;
+                match symbol {
+                    'M' => level.spawn_points.push(Point::new(tx, ty)),
+                    '+' => level.doors.push((Point::new(tx, ty), false)),
+                    '=' => level.doors.push((Point::new(tx, ty), true)),
+                    _ => {}
+                }
+            }
+        }
+    }
+}
+
+
+/// Resource holding every prefab the generators may use.
+#[derive(Clone, Debug, Default)]
+pub struct PrefabLibrary {
+    pub prefabs: Vec<Prefab>,
+}
+
+impl PrefabLibrary {
+    pub fn new() -> PrefabLibrary {
+        PrefabLibrary { prefabs: Vec::new() }
+    }
+
+    ///loads every `.txt` file in `dir`
+    pub fn load_dir(dir: impl AsRef<Path>) -> Result<PrefabLibrary, PrefabError> {
+        let mut paths : Vec<_> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+            .collect();
+        // directory order differs between machines, the same seed has to pick the same prefabs everywhere
+        paths.sort();
+
+        let mut library = PrefabLibrary::new();
+        for path in paths {
+            let text = fs::read_to_string(&path)?;
+            library.prefabs.push(Prefab::parse(&path.display().to_string(), &text)?);
+        }
+        Ok(library)
+    }
+
+    ///a prefab allowed at `depth`, picked at random by weight
+    pub fn pick(&self, rng: &mut GameRng, depth: i32) -> Option<&Prefab> {
+        let allowed = || self.prefabs.iter().filter(|p| p.weight > 0 && depth >= p.min_depth && depth <= p.max_depth);
+        let total : u32 = allowed().map(|p| p.weight).sum();
+        if total == 0 { return None; }
+        let mut roll = rng.gen_range(0..total);
+        for prefab in allowed() {
+            if roll < prefab.weight { return Some(prefab); }
+            roll -= prefab.weight;
+        }
+        None
+    }
+
+    ///tries to stamp up to `count` prefabs into solid rock on `level`, digs a corridor out of each door
+    ///and connects whatever is still cut off. returns how many were placed
+    pub fn stamp_prefabs(&self, level: &mut BuiltLevel, rng: &mut GameRng, count: usize) -> usize {
+        const PLACEMENT_TRIES : usize = 50;
+
+        let mut placed = 0;
+        for _ in 0..count {
+            let Some(prefab) = self.pick(rng, level.map.depth) else { break; };
+            let prefab = prefab.random_transform(rng);
+            if prefab.width + 2 >= level.map.width || prefab.height + 2 >= level.map.height { continue; }
+            for _ in 0..PLACEMENT_TRIES {
+                let x = rng.gen_range(1..level.map.width - prefab.width);
+                let y = rng.gen_range(1..level.map.height - prefab.height);
+                if prefab.fits(&level.map, x, y) {
+                    let first_door = level.doors.len();
+                    prefab.stamp(level, x, y);
+                    for (door, _) in level.doors[first_door..].iter().copied() {
+                        dig_from_door(&mut level.map, door, Rect::new(x, y, prefab.width - 1, prefab.height - 1));
+                    }
+                    placed += 1;
+                    break;
+                }
+            }
+        }
+        if placed > 0 {
+            repair_connectivity(level);
+        }
+        placed
+    }
+}
+
+///digs the shortest corridor from `door` to the nearest tile outside `footprint` that can be walked on,
+///going around the prefab rather than back through it
+fn dig_from_door(map: &mut Map, door: Point, footprint: Rect) {
+    let inside = |x: i32, y: i32| x >= footprint.x1 && x <= footprint.x2 && y >= footprint.y1 && y <= footprint.y2;
+    let start = map.xy_idx(door.x, door.y);
+    let mut came_from = vec![usize::MAX; map.tiles.len()];
+    came_from[start] = start;
+    let mut open = VecDeque::from([start]);
+
+    while let Some(idx) = open.pop_front() {
+        let (x, y) = (idx as i32 % map.width, idx as i32 / map.width);
+        if idx != start && map.tiles[idx].move_cost() != MoveCost::Impassable {
+            let mut step = came_from[idx];
+            while step != start {
+                map.tiles[step] = TileType::Floor;
+                step = came_from[step];
+            }
+            return;
+        }
+        for (dx, dy) in [(0, -1), (0, 1), (-1, 0), (1, 0)] {
+            let (nx, ny) = (x + dx, y + dy);
+            // stay off the outermost tiles, the map has to stay closed in
+            if nx < 1 || ny < 1 || nx >= map.width - 1 || ny >= map.height - 1 || inside(nx, ny) { continue; }
+            let next = map.xy_idx(nx, ny);
+            if came_from[next] == usize::MAX {
+                came_from[next] = idx;
+                open.push_back(next);
+            }
+        }
+    }
+}
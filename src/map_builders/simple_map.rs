@@ -0,0 +1,69 @@
+use rand::Rng;
+
+use super::{MapBuilder, BuiltLevel, finish_room_level};
+use crate::{Map, Point, Rect, GameRng};
+
+
+/// The original layout: up to 30 rooms of 6 to 10 tiles thrown at the map, each joined to the
+/// previous one by an L-shaped corridor.
+pub struct SimpleMapBuilder {
+    pub max_rooms: i32,
+    pub min_size: i32,
+    pub max_size: i32,
+}
+
+impl Default for SimpleMapBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SimpleMapBuilder {
+    pub fn new() -> SimpleMapBuilder {
+        SimpleMapBuilder { max_rooms: 30, min_size: 6, max_size: 10 }
+    }
+}
+
+impl MapBuilder for SimpleMapBuilder {
+    fn build_map(&mut self, rng: &mut GameRng, depth: i32, size: Point) -> BuiltLevel {
+        let mut map = Map::new(depth, size.x, size.y);
+
+        for _ in 0..self.max_rooms {
+            let w = rng.gen_range(self.min_size..self.max_size);
+            let h = rng.gen_range(self.min_size..self.max_size);
+            // doesn't fit on a map this small
+            if map.width - w - 1 <= 1 || map.height - h - 1 <= 1 { continue; }
+            let x = rng.gen_range(1..(map.width - w - 1)) - 1;
+            let y = rng.gen_range(1..(map.height - h - 1)) - 1;
+            let new_room = Rect::new(x, y, w, h);
+            let mut ok = true;
+            for other_room in map.rooms.iter() {
+                if new_room.intersect(other_room) { ok = false }
+            }
+            if ok {
+                map.apply_room_to_map(&new_room);
+
+                if !map.rooms.is_empty() {
+                    let (new_x, new_y) = new_room.center();
+                    let (prev_x, prev_y) = map.rooms[map.rooms.len()-1].center();
+                    if rng.gen_range(0..2) == 1 {
+                        map.apply_horizontal_tunnel(prev_x, new_x, prev_y);
+                        map.apply_vertical_tunnel(prev_y, new_y, new_x);
+                    } else {
+                        map.apply_vertical_tunnel(prev_y, new_y, prev_x);
+                        map.apply_horizontal_tunnel( prev_x, new_x, new_y);
+                    }
+                }
+
+                map.rooms.push(new_room);
+            }
+        }
+        if map.rooms.is_empty() {
+            let room = Rect::new(0, 0, map.width - 2, map.height - 2);
+            map.apply_room_to_map(&room);
+            map.rooms.push(room);
+        }
+
+        finish_room_level(map, rng)
+    }
+}
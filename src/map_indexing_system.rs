@@ -1,5 +1,5 @@
 use specs::prelude::*;
-use super::{Map, Position, BlocksTile};
+use super::{Map, Position, BlocksTile, Door};
 
 pub struct MapIndexingSystem {}
 
@@ -7,13 +7,19 @@ impl<'a> System<'a> for MapIndexingSystem {
     type SystemData = ( WriteExpect<'a, Map>,
                         ReadStorage<'a, Position>,
                         ReadStorage<'a, BlocksTile>,
+                        ReadStorage<'a, Door>,
                         Entities<'a>,);
 
     fn run(&mut self, data : Self::SystemData) {
-        let (mut map, position, blockers, entities) = data;
+        let (mut map, position, blockers, doors, entities) = data;
 
         map.populate_blocked();
         map.clear_content_index();
+        for door in map.doors.iter_mut() { *door = None; }
+        for (position, door) in (&position, &doors).join() {
+            let idx = map.xy_idx(position.x, position.y);
+            map.doors[idx] = Some(door.state());
+        }
         for (entity,position) in (&entities,&position).join() {
             let idx = map.xy_idx(position.x, position.y);
 
@@ -1,5 +1,6 @@
+use rand::Rng;
 use specs::prelude::*;
-use super::{CombatStats, WantsToMelee, Name, SufferDamage, EventLog};
+use super::{CombatStats, WantsToMelee, Name, SufferDamage, EventLog, GameRng, Position, Noises, Point, COMBAT_NOISE, Map, Faction, FactionTable, Reaction};
 
 pub struct MeleeCombatSystem {}
 
@@ -9,24 +10,48 @@ impl<'a> System<'a> for MeleeCombatSystem {
                         ReadStorage<'a, Name>,
                         ReadStorage<'a, CombatStats>,
                         WriteStorage<'a, SufferDamage>,
-                        WriteExpect<'a, EventLog>
+                        WriteExpect<'a, EventLog>,
+                        WriteExpect<'a, GameRng>,
+                        ReadStorage<'a, Position>,
+                        ReadExpect<'a, Entity>,
+                        WriteExpect<'a, Noises>,
+                        ReadExpect<'a, Map>,
+                        ReadStorage<'a, Faction>,
+                        ReadExpect<'a, FactionTable>
                       );
 
     fn run(&mut self, data : Self::SystemData) {
-        let (entities, mut wants_melee, names, combat_stats, mut inflict_damage, mut log) = data;
+        let (entities, mut wants_melee, names, combat_stats, mut inflict_damage, mut log, mut rng, positions, player, mut noises, map, factions, table) = data;
 
-        for (_entity, wants_melee, name, stats) in (&entities, &wants_melee, &names, &combat_stats).join() {
+        for (entity, wants_melee, name, stats) in (&entities, &wants_melee, &names, &combat_stats).join() {
+            // only ever swings at something it's hostile to
+            if table.between(factions.get(entity), factions.get(wants_melee.target)) != Reaction::Hostile { continue; }
             if stats.hp > 0 {
                 let target_stats = combat_stats.get(wants_melee.target).unwrap();
                 if target_stats.hp > 0 {
                     let target_name = names.get(wants_melee.target).unwrap();
+                    // a fight the player is in can be heard, and tells whoever hears it where the player is
+                    if entity == *player || wants_melee.target == *player {
+                        if let Some(pos) = positions.get(wants_melee.target) {
+                            noises.make(Point::new(pos.x, pos.y), COMBAT_NOISE);
+                        }
+                    }
 
-                    let damage = i32::max(0, stats.power - target_stats.defense);
+                    // a blow lands a point harder or softer than usual now and then
+                    let roll = rng.gen_range(-1..=1);
+                    let damage = i32::max(0, stats.power + roll - target_stats.defense);
+                    // monsters fighting each other out of sight go unreported
+                    let seen = entity == *player || wants_melee.target == *player
+                        || positions.get(wants_melee.target).is_some_and(|pos| map.visible_tiles[map.xy_idx(pos.x, pos.y)]);
 
                     if damage == 0 {
-                        log.message(format!("{} is unable to hurt {}", &name.name, &target_name.name));
+                        if seen {
+                            log.message(format!("{} is unable to hurt {}", &name.name, &target_name.name));
+                        }
                     } else {
-                        log.message(format!("{} hits {}, for {} hp.", &name.name, &target_name.name, damage));
+                        if seen {
+                            log.message(format!("{} hits {}, for {} hp.", &name.name, &target_name.name, damage));
+                        }
                         SufferDamage::new_damage(&mut inflict_damage, wants_melee.target, damage);
                     }
                 }
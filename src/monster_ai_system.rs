@@ -1,7 +1,32 @@
+use rand::Rng;
 use specs::prelude::*;
-use super::{a_star_search,a_star_search_cardinal};
 
-use super::{Viewshed, Position, Map, Monster, Point, RunState, WantsToMelee};
+use super::{Viewshed, Position, Map, Monster, AiState, Point, WantsToMelee, DijkstraCache, DijkstraMap, AStarPathfinder, MovementProfile,
+            MoveCost, BaseMap, CombatStats, BlocksTile, Door, Renderable, Energy, MyTurn, ActionCost, GameRng, Faction, FactionTable, Reaction};
+
+/// How far (in movement cost) monsters will track a remembered player position
+const CHASE_DEPTH : f32 = 100.0;
+/// Monsters run for it once they're down to this much of their health
+const FLEE_HEALTH : f32 = 0.25;
+/// A fleeing monster stops running once it's this far (in movement cost) from the player
+pub const FLEE_DISTANCE : f32 = 12.0;
+/// Turns spent poking around where the player was last seen before giving up
+const SEARCH_TURNS : i32 = 10;
+/// How far from the last sighting a search strays
+const SEARCH_RADIUS : i32 = 4;
+/// How far from home a wandering monster strays
+pub const WANDER_RADIUS : i32 = 6;
+/// Chance in a hundred an idle monster sets off wandering on its turn
+const WANDER_CHANCE : i32 = 5;
+/// Allies of the player don't bother catching up while they're this close to it
+const FOLLOW_DISTANCE : f32 = 2.5;
+
+/// What a monster ends up doing with its turn
+enum Intent {
+    Attack(Entity),
+    Step(usize),
+    Rest,
+}
 
 pub struct MonsterAI {}
 
@@ -14,43 +39,227 @@ impl<'a> System<'a> for MonsterAI {
                         ReadExpect<'a, Entity>,
                         Entities<'a>,
                         WriteStorage<'a, WantsToMelee>,
-                        ReadExpect<'a, RunState>);
+                        WriteExpect<'a, DijkstraCache>,
+                        WriteExpect<'a, AStarPathfinder>,
+                        WriteExpect<'a, GameRng>,
+                        ReadStorage<'a, MovementProfile>,
+                        ReadStorage<'a, CombatStats>,
+                        ReadStorage<'a, BlocksTile>,
+                        WriteStorage<'a, Door>,
+                        WriteStorage<'a, Renderable>,
+                        WriteStorage<'a, Energy>,
+                        WriteStorage<'a, MyTurn>,
+                        ReadStorage<'a, Faction>,
+                        ReadExpect<'a, FactionTable>);
 
     fn run(&mut self, data : Self::SystemData) {
-        let (mut map,player_pos ,mut viewshed, mut monster, mut position,   player_entity, entities, mut wants_to_melee, runstate) = data;
-        
-        if *runstate != RunState::MonsterTurn { return; }
-
-        for (entity,mut viewshed,mut monster,  mut pos) in (&entities, &mut viewshed, &mut monster,  &mut position).join() {
-            let distance = Map::distance2d_pythagoras(pos.x, pos.y, player_pos.x, player_pos.y);
-            if distance < 1.5 {
-                wants_to_melee.insert(entity, WantsToMelee{ target: *player_entity }).expect("Unable to insert attack");
-                return;
-            }
-            if viewshed.visible_tiles.contains(&(player_pos.x,player_pos.y)) {
-                monster.seen_player = true;
-                monster.known_player_location = (player_pos.x.clone(), player_pos.y.clone());
+        let (mut map,player_pos ,mut viewshed, mut monster, mut position,   player_entity, entities, mut wants_to_melee, mut dijkstra, mut pathfinder, mut rng,
+             profiles, combat_stats, blockers, mut doors, mut renderables, mut energies, mut turns, factions, table) = data;
+
+        if turns.is_empty() { return; }
+        // things have moved since the last monsters acted
+        dijkstra.clear();
+        // everything that could be fought, and where it stands, kept up to date as monsters move
+        let mut creatures : Vec<(Entity, i32, i32)> = (&entities, &position, &combat_stats).join()
+            .map(|(entity, pos, _)| (entity, pos.x, pos.y))
+            .collect();
+
+        for (entity,viewshed,monster,pos,energy) in (&entities, &mut viewshed, &mut monster,  &mut position, &mut energies).join() {
+            // only monsters picked by `next_turn` act
+            if turns.remove(entity).is_none() { continue; }
+            let here = (pos.x, pos.y);
+            let home = *monster.home.get_or_insert(here);
+            // goes for the closest foe in sight, a sleeper has its eyes shut
+            let eyes = (monster.state != AiState::Asleep).then_some(&*viewshed);
+            let Foes { visible: target, adjacent: foe_adjacent } = find_foes(entity, here, eyes, creatures.iter().copied(), &factions, &table);
+            if let Some((_, x, y)) = target {
+                monster.known_player_location = (x, y);
             }
+            let ally = table.between(factions.get(entity), factions.get(*player_entity)) == Reaction::Friendly;
+            let hurt = combat_stats.get(entity).is_some_and(|stats| stats.hp as f32 <= stats.max_hp as f32 * FLEE_HEALTH);
+            monster.state = next_state(monster, here, target.is_some(), hurt, ally);
 
-            if monster.seen_player {
-                if (pos.x, pos.y) == monster.known_player_location {
-                    monster.seen_player = false;
-                    return;
+            let intent = {
+                let profile = profiles.get(entity);
+                let walker = map.walker_for(profile);
+                let here_idx = map.xy_idx(pos.x, pos.y);
+                let known = map.xy_idx(monster.known_player_location.0, monster.known_player_location.1);
+                let mut path_to = |(x, y): (i32, i32)| -> Option<usize> {
+                    let path = pathfinder.search(here_idx, map.xy_idx(x, y), &walker);
+                    if path.success { path.steps.get(1).copied() } else { None }
+                };
+
+                match monster.state {
+                    AiState::Chase => match foe_adjacent {
+                        Some(foe) => Intent::Attack(foe),
+                        None => match field_from(&mut dijkstra, known, &map, profile).find_lowest_exit(here_idx, &walker) {
+                            Some(step) => Intent::Step(step),
+                            None => {
+                                // no way through to it, look around instead
+                                monster.state = AiState::Search { turns: SEARCH_TURNS };
+                                Intent::Rest
+                            }
+                        },
+                    },
+                    AiState::Flee => {
+                        let field = field_from(&mut dijkstra, known, &map, profile);
+                        let closest = field.find_lowest_exit(here_idx, &walker);
+                        let furthest = field.find_highest_exit(here_idx, &walker);
+                        match (closest, furthest) {
+                            (Some(closest), _) if field.map[closest] >= FLEE_DISTANCE => Intent::Rest,
+                            (Some(closest), Some(furthest)) if field.map[furthest] > field.map[closest] => Intent::Step(furthest),
+                            // every way out leads back towards what it's running from, so it's cornered and fights
+                            _ => match foe_adjacent {
+                                Some(foe) => Intent::Attack(foe),
+                                None => Intent::Rest,
+                            },
+                        }
+                    }
+                    AiState::Search { .. } => {
+                        let (kx, ky) = monster.known_player_location;
+                        let exits : Vec<usize> = walker.get_available_exits(here_idx).into_iter()
+                            .map(|(exit, _)| exit)
+                            .filter(|exit| {
+                                let (x, y) = (*exit as i32 % map.width, *exit as i32 / map.width);
+                                (x - kx).abs() <= SEARCH_RADIUS && (y - ky).abs() <= SEARCH_RADIUS
+                            })
+                            .collect();
+                        if exits.is_empty() { Intent::Rest } else { Intent::Step(exits[rng.gen_range(0..exits.len())]) }
+                    }
+                    AiState::Wander { x, y } => match path_to((x, y)) {
+                        Some(step) => Intent::Step(step),
+                        None => { monster.state = AiState::Idle; Intent::Rest }
+                    },
+                    AiState::ReturnHome => match path_to(home) {
+                        Some(step) => Intent::Step(step),
+                        // home is taken or cut off, settle down here
+                        None => { monster.state = AiState::Idle; Intent::Rest }
+                    },
+                    AiState::Idle if rng.gen_range(0..100) < WANDER_CHANCE => {
+                        let target = (0..10)
+                            .map(|_| (home.0 + rng.gen_range(-WANDER_RADIUS..=WANDER_RADIUS), home.1 + rng.gen_range(-WANDER_RADIUS..=WANDER_RADIUS)))
+                            .find(|(x, y)| {
+                                if !map.in_bounds(*x, *y) || (*x, *y) == here { return false; }
+                                let idx = map.xy_idx(*x, *y);
+                                let tile = map.tiles[idx];
+                                let cost = profile.map_or_else(|| tile.move_cost(), |profile| profile.cost(tile));
+                                !map.blocked[idx] && cost != MoveCost::Impassable
+                            });
+                        match target.and_then(|target| path_to(target).map(|step| (target, step))) {
+                            Some(((x, y), step)) => { monster.state = AiState::Wander { x, y }; Intent::Step(step) }
+                            None => Intent::Rest,
+                        }
+                    }
+                    AiState::Follow if Map::distance2d_pythagoras(pos.x, pos.y, player_pos.x, player_pos.y) > FOLLOW_DISTANCE => {
+                        match path_to((player_pos.x, player_pos.y)) {
+                            Some(step) => Intent::Step(step),
+                            None => Intent::Rest,
+                        }
+                    }
+                    AiState::Idle | AiState::Asleep | AiState::Follow => Intent::Rest,
                 }
-                let path = a_star_search(
-                map.xy_idx(pos.x, pos.y) as i32,
-                map.xy_idx(monster.known_player_location.0, monster.known_player_location.1) as i32,
-                &mut *map
-                );
-                
-
-                if path.success && path.steps.len()>1{
-                    pos.x = path.steps[1] as i32 % map.width;
-                    pos.y = path.steps[1] as i32 / map.width;
-                    viewshed.dirty = true;
+            };
+
+            match intent {
+                Intent::Attack(target) => {
+                    wants_to_melee.insert(entity, WantsToMelee{ target }).expect("Unable to insert attack");
+                    energy.spend(ActionCost::Attack);
                 }
-                
+                Intent::Step(step) => match take_step(step, pos, blockers.contains(entity), *player_pos, &mut map, &mut doors, &mut renderables) {
+                    Some(cost) => {
+                        energy.spend(cost);
+                        viewshed.dirty = true;
+                        if let Some(creature) = creatures.iter_mut().find(|(other, _, _)| *other == entity) {
+                            *creature = (entity, pos.x, pos.y);
+                        }
+                    }
+                    // the player's in the way, wait for it to move
+                    None => energy.spend(ActionCost::Rest),
+                },
+                Intent::Rest => energy.spend(ActionCost::Rest),
             }
         }
     }
 }
+
+///moves a monster standing on `pos` onto the tile `step`, or opens the closed door there instead, and returns
+///what that cost. `blocks` is whether it has `BlocksTile`. None, and it stays put, if `step` is where the player
+///stands: the player doesn't block its tile, and nothing walks into it whatever it thinks of the player
+pub fn take_step(step: usize, pos: &mut Position, blocks: bool, player: Point, map: &mut Map, doors: &mut WriteStorage<Door>,
+                 renderables: &mut WriteStorage<Renderable>) -> Option<ActionCost> {
+    if step == map.xy_idx(player.x, player.y) { return None; }
+    let door = map.tile_content[step].iter().copied().find(|e| doors.get(*e).is_some_and(|d| !d.open));
+    if let Some(door) = door {
+        // paths only lead through closed doors for monsters that can open them, that takes the turn
+        doors.get_mut(door).expect("door without a Door").set_open(true, step, map, renderables.get_mut(door));
+        return Some(ActionCost::Door);
+    }
+    let from = map.xy_idx(pos.x, pos.y);
+    pos.x = step as i32 % map.width;
+    pos.y = step as i32 / map.width;
+    // keep the ones still to move this turn from stepping onto the same tile
+    if blocks {
+        map.blocked[from] = false;
+        map.blocked[step] = true;
+    }
+    Some(ActionCost::Move)
+}
+
+/// What a monster makes of the creatures around it
+pub struct Foes {
+    /// the closest thing it can see that it's hostile to, and where that stands
+    pub visible: Option<(Entity, i32, i32)>,
+    /// something it's hostile to standing right next to it, the one it sees if that's close enough
+    pub adjacent: Option<Entity>,
+}
+
+///sizes up `creatures`, everything that could be fought and where it stands, from `here` where `entity` stands.
+///`viewshed` is what it can see, None while its eyes are shut. Both `MonsterAI` and the behaviour trees pick their
+///foes with this
+pub fn find_foes(entity: Entity, (x, y): (i32, i32), viewshed: Option<&Viewshed>, creatures: impl IntoIterator<Item = (Entity, i32, i32)>,
+                 factions: &ReadStorage<Faction>, table: &FactionTable) -> Foes {
+    let mine = factions.get(entity);
+    let foes : Vec<(Entity, i32, i32)> = creatures.into_iter()
+        .filter(|(other, _, _)| *other != entity && table.between(mine, factions.get(*other)) == Reaction::Hostile)
+        .collect();
+    let visible = foes.iter().copied()
+        .filter(|(_, fx, fy)| viewshed.is_some_and(|viewshed| viewshed.visible_tiles.contains(Point::new(*fx, *fy))))
+        .min_by_key(|(_, fx, fy)| (fx - x).pow(2) + (fy - y).pow(2));
+    let next_to = |(_, fx, fy): &(Entity, i32, i32)| Map::distance2d_pythagoras(x, y, *fx, *fy) < 1.5;
+    let adjacent = visible.filter(next_to).or_else(|| foes.iter().copied().find(next_to)).map(|(foe, _, _)| foe);
+    Foes { visible, adjacent }
+}
+
+///the distance field flowing out from `target`, priced for `profile`. monsters heading for or away from
+///the same spot share one
+pub fn field_from<'a>(dijkstra: &'a mut DijkstraCache, target: usize, map: &Map, profile: Option<&MovementProfile>) -> &'a DijkstraMap {
+    match profile {
+        Some(profile) => dijkstra.get_or_build_profiled(&[target], map, profile, CHASE_DEPTH),
+        None => dijkstra.get_or_build(&[target], map, CHASE_DEPTH),
+    }
+}
+
+///picks what the monster is up to this turn from whether it sees anything it's hostile to, how hurt it is,
+///what it remembers and whether it's on the player's side
+fn next_state(monster: &Monster, here: (i32, i32), sees_foe: bool, hurt: bool, ally: bool) -> AiState {
+    let state = match monster.state {
+        // only a noise wakes it, see HearingSystem
+        AiState::Asleep => AiState::Asleep,
+        _ if hurt && (sees_foe || monster.state == AiState::Flee) => AiState::Flee,
+        _ if sees_foe => AiState::Chase,
+        AiState::Chase if here == monster.known_player_location => AiState::Search { turns: SEARCH_TURNS },
+        AiState::Search { turns } if turns <= 0 => AiState::ReturnHome,
+        AiState::Search { turns } => AiState::Search { turns: turns - 1 },
+        // patched up and whatever it ran from is nowhere to be seen
+        AiState::Flee => AiState::ReturnHome,
+        AiState::Wander { x, y } if here == (x, y) => AiState::Idle,
+        AiState::ReturnHome if Some(here) == monster.home => AiState::Idle,
+        state => state,
+    };
+    match state {
+        // the player's allies stick with it rather than going off on their own
+        AiState::Idle | AiState::Wander { .. } | AiState::Search { .. } | AiState::ReturnHome if ally => AiState::Follow,
+        AiState::Follow if !ally => AiState::ReturnHome,
+        state => state,
+    }
+}
@@ -1,78 +1,316 @@
+#[cfg(feature = "render")]
 use raylib::prelude::*;
 use crate::Point;
 use crate::RunState;
 use crate::WantsToMelee;
+use crate::EventLog;
 
-use super::{Position, Player, TileType,Map,  State, Viewshed, CombatStats, EventLog};
-use std::cmp::{min, max};
+use super::{Position, Map, Viewshed, CombatStats, MoveCost, MovementProfile, TileType, Renderable, Name,
+            Door, DoorState, Item, Key, InBackpack, Energy, ActionCost, Noises, DOOR_NOISE, RUN_NOISE, Faction, FactionTable, Reaction,
+            Dialogue, GameRng};
+use rand::Rng;
+use serde::{Serialize, Deserialize};
 use specs::prelude::*;
 
-pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) {
+///moves, attacks, talks or opens a door, and returns what the turn was spent on. None if the way was blocked,
+///which doesn't use up a turn
+pub fn try_move_player(delta_x: i32, delta_y: i32, ecs: &mut World) -> Option<ActionCost> {
+    let destination = {
+        let map = ecs.fetch::<Map>();
+        let pos = ecs.fetch::<Point>();
+        let (x, y) = (pos.x + delta_x, pos.y + delta_y);
+        if x < 1 || x > map.width-1 || y < 1 || y > map.height-1 { return None; }
+        map.xy_idx(x, y)
+    };
+    if try_door(ecs, destination) { return Some(ActionCost::Door); }
+    match step_player(delta_x, delta_y, ecs) {
+        Bump::Moved => {
+            pick_up_items(ecs);
+            Some(ActionCost::Move)
+        }
+        Bump::Attacked => Some(ActionCost::Attack),
+        // a word in passing gets nothing done
+        Bump::Talked => Some(ActionCost::Rest),
+        Bump::Blocked => None,
+    }
+}
+
+/// What walking into a tile came to
+enum Bump {
+    Moved,
+    Attacked,
+    Talked,
+    Blocked,
+}
+
+///attacks whatever hostile is in the way, talks to anything neutral, swaps places with anything friendly, and moves
+///there if nothing was in the way. anything that ignores the player stays put and blocks it
+fn step_player(delta_x: i32, delta_y: i32, ecs: &mut World) -> Bump {
+    let player = *ecs.fetch::<Entity>();
     let mut positions = ecs.write_storage::<Position>();
-    let mut players = ecs.write_storage::<Player>();
     let mut viewsheds = ecs.write_storage::<Viewshed>();
     let combat_stats = ecs.read_storage::<CombatStats>();
-    let mut log = ecs.fetch_mut::<EventLog>();
+    let profiles = ecs.read_storage::<MovementProfile>();
+    let factions = ecs.read_storage::<Faction>();
+    let table = ecs.fetch::<FactionTable>();
     let map = ecs.fetch::<Map>();
-    let entites = ecs.entities();
     let mut wants_to_melee = ecs.write_storage::<WantsToMelee>();
 
-    for (entity,_player, pos, viewshed) in (&entites,&mut players, &mut positions, &mut viewsheds).join() {
-        //if the spot is vacant, or occupied by a wall
-        if pos.x + delta_x < 1 || pos.x + delta_x > map.width-1 || pos.y + delta_y < 1 || pos.y + delta_y > map.height-1 { return; }
-        let destination_idx = map.xy_idx(pos.x + delta_x, pos.y + delta_y);
-        
-            for potential_target in map.tile_content[destination_idx].iter() {
-                let target = combat_stats.get(*potential_target);
-                if let Some(_target) = target {
-                    wants_to_melee.insert(entity, WantsToMelee{ target: *potential_target }).expect("Add target failed");
-                    return;
+    let Some((from_x, from_y)) = positions.get(player).map(|pos| (pos.x, pos.y)) else { return Bump::Blocked; };
+    let (x, y) = (from_x + delta_x, from_y + delta_y);
+    if x < 1 || x > map.width-1 || y < 1 || y > map.height-1 { return Bump::Blocked; }
+    let destination_idx = map.xy_idx(x, y);
+    let terrain = map.tiles[destination_idx];
+    let cost = profiles.get(player).map_or_else(|| terrain.move_cost(), |p| p.cost(terrain));
+
+    let in_the_way = map.tile_content[destination_idx].iter().copied().find(|e| combat_stats.contains(*e));
+    let swapped = match in_the_way {
+        None => false,
+        Some(other) => match table.between(factions.get(player), factions.get(other)) {
+            Reaction::Hostile => {
+                wants_to_melee.insert(player, WantsToMelee{ target: other }).expect("Add target failed");
+                return Bump::Attacked;
+            }
+            Reaction::Neutral => {
+                talk_to(ecs, other);
+                return Bump::Talked;
+            }
+            // it won't make way for someone it pays no mind to
+            Reaction::Ignore => return Bump::Blocked,
+            Reaction::Friendly => {
+                // it takes the player's place, if it can stand there and the player can stand where it was
+                let tile = map.tiles[map.xy_idx(from_x, from_y)];
+                let its_cost = profiles.get(other).map_or_else(|| tile.move_cost(), |p| p.cost(tile));
+                if cost == MoveCost::Impassable || its_cost == MoveCost::Impassable { return Bump::Blocked; }
+                if let Some(pos) = positions.get_mut(other) {
+                    *pos = Position { x: from_x, y: from_y };
                 }
+                if let Some(viewshed) = viewsheds.get_mut(other) {
+                    viewshed.dirty = true;
+                }
+                true
             }
-        if !map.blocked[destination_idx] {
-            pos.x = min(79 , max(0, pos.x + delta_x));
-            pos.y = min(49, max(0, pos.y + delta_y));
-            let mut ppos = ecs.write_resource::<Point>();
-            ppos.x = pos.x;
-            ppos.y = pos.y;
+        },
+    };
 
+    if (swapped || !map.blocked[destination_idx]) && cost != MoveCost::Impassable {
+        if let Some(pos) = positions.get_mut(player) {
+            *pos = Position { x, y };
+        }
+        *ecs.write_resource::<Point>() = Point::new(x, y);
+        if let Some(viewshed) = viewsheds.get_mut(player) {
             viewshed.dirty = true;
         }
+        return Bump::Moved;
+    }
+    Bump::Blocked
+}
+
+///has `other` say one of its lines, or nothing much if it has none
+fn talk_to(ecs: &World, other: Entity) {
+    let names = ecs.read_storage::<Name>();
+    let dialogues = ecs.read_storage::<Dialogue>();
+    let name = names.get(other).map_or("it", |name| name.name.as_str());
+    let message = match dialogues.get(other).filter(|dialogue| !dialogue.lines.is_empty()) {
+        Some(dialogue) => {
+            let line = ecs.write_resource::<GameRng>().gen_range(0..dialogue.lines.len());
+            format!("The {} says: \"{}\"", name, dialogue.lines[line])
+        }
+        None => format!("The {} has nothing to say.", name),
+    };
+    ecs.fetch_mut::<EventLog>().message(message);
+}
+
+///bumping into a closed door opens it, a locked one takes a key from the backpack to open.
+///returns true if there was a shut door in the way, which uses up the move
+fn try_door(ecs: &mut World, idx: usize) -> bool {
+    let player = *ecs.fetch::<Entity>();
+    let mut map = ecs.fetch_mut::<Map>();
+    let mut doors = ecs.write_storage::<Door>();
+    let mut renderables = ecs.write_storage::<Renderable>();
+    let mut log = ecs.fetch_mut::<EventLog>();
+
+    let Some(door_entity) = map.tile_content[idx].iter().copied().find(|e| doors.contains(*e)) else { return false; };
+    let door = doors.get_mut(door_entity).expect("door without a Door");
+    match door.state() {
+        DoorState::Open => return false,
+        DoorState::Closed => log.message("You open the door.".to_string()),
+        DoorState::Locked => {
+            let entities = ecs.entities();
+            let keys = ecs.read_storage::<Key>();
+            let backpacks = ecs.read_storage::<InBackpack>();
+            let key = (&entities, &keys, &backpacks).join()
+                .find(|(_, _, pack)| pack.owner == player)
+                .map(|(entity, _, _)| entity);
+            match key {
+                Some(key) => {
+                    entities.delete(key).expect("Unable to delete");
+                    log.message("You unlock the door.".to_string());
+                }
+                None => {
+                    log.message("The door is locked.".to_string());
+                    return true;
+                }
+            }
+        }
+    }
+    // the map's revision changes with the door, so everyone looks again next turn
+    door.set_open(true, idx, &mut map, renderables.get_mut(door_entity));
+    ecs.fetch_mut::<Noises>().make(Point::new(idx as i32 % map.width, idx as i32 / map.width), DOOR_NOISE);
+    true
+}
+
+///puts whatever lies where the player stands into the player's backpack
+fn pick_up_items(ecs: &mut World) {
+    let player = *ecs.fetch::<Entity>();
+    let pos = *ecs.fetch::<Point>();
+    let map = ecs.fetch::<Map>();
+    let items = ecs.read_storage::<Item>();
+    let names = ecs.read_storage::<Name>();
+    let mut positions = ecs.write_storage::<Position>();
+    let mut backpacks = ecs.write_storage::<InBackpack>();
+    let mut log = ecs.fetch_mut::<EventLog>();
+
+    for item in map.tile_content[map.xy_idx(pos.x, pos.y)].iter().filter(|e| items.contains(**e)) {
+        positions.remove(*item);
+        backpacks.insert(*item, InBackpack { owner: player }).expect("Unable to insert");
+        if let Some(name) = names.get(*item) {
+            log.message(format!("You pick up the {}.", name.name));
+        }
+    }
+}
+
+///closes an open door next to the player, if there's one with nothing standing in it
+fn try_close_door(ecs: &mut World) -> RunState {
+    let pos = *ecs.fetch::<Point>();
+    let mut map = ecs.fetch_mut::<Map>();
+    let mut doors = ecs.write_storage::<Door>();
+    let mut renderables = ecs.write_storage::<Renderable>();
+
+    for (dx, dy) in [(0, -1), (0, 1), (-1, 0), (1, 0), (-1, -1), (1, -1), (-1, 1), (1, 1)] {
+        if !map.in_bounds(pos.x + dx, pos.y + dy) { continue; }
+        let idx = map.xy_idx(pos.x + dx, pos.y + dy);
+        // a door can only be shut on an empty doorway
+        let [door_entity] = map.tile_content[idx][..] else { continue; };
+        let Some(door) = doors.get_mut(door_entity) else { continue; };
+        if !door.open { continue; }
+        door.set_open(false, idx, &mut map, renderables.get_mut(door_entity));
+        ecs.fetch_mut::<EventLog>().message("You close the door.".to_string());
+        ecs.fetch_mut::<Noises>().make(Point::new(pos.x + dx, pos.y + dy), DOOR_NOISE);
+        return RunState::PlayerTurn;
+    }
+    ecs.fetch_mut::<EventLog>().message("There is no open door next to you.".to_string());
+    RunState::AwaitingInput
+}
+
+/// A command the player can issue, independent of where it came from (keyboard, script, test)
+#[derive(PartialEq, Eq, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum PlayerAction {
+    Move { dx: i32, dy: i32 },
+    /// move quicker than walking, but loud enough for anything nearby to hear
+    Run { dx: i32, dy: i32 },
+    /// take the stairs down, only works standing on them
+    Descend,
+    /// take the stairs up, only works standing on them
+    Ascend,
+    /// shut an open door next to the player
+    CloseDoor,
+    /// wait a moment, half as long as a step
+    Rest,
+}
+
+/// Applies a player command to the world and returns the run state that follows it
+pub fn apply_player_action(ecs: &mut World, action: PlayerAction) -> RunState {
+    let cost = match action {
+        PlayerAction::Move { dx, dy } | PlayerAction::Run { dx, dy } => {
+            // walking into a wall takes no time, the player just picks something else to do
+            let Some(cost) = try_move_player(dx, dy, ecs) else { return RunState::AwaitingInput; };
+            match (action, cost) {
+                // only an actual step is any quicker or louder at a run, bumping into something is the same at any pace
+                (PlayerAction::Run { .. }, ActionCost::Move) => {
+                    let pos = *ecs.fetch::<Point>();
+                    ecs.fetch_mut::<Noises>().make(pos, RUN_NOISE);
+                    ActionCost::Run
+                }
+                (_, cost) => cost,
+            }
+        }
+        PlayerAction::Descend => return try_stairs(ecs, TileType::DownStairs),
+        PlayerAction::Ascend => return try_stairs(ecs, TileType::UpStairs),
+        PlayerAction::CloseDoor => {
+            let state = try_close_door(ecs);
+            if state != RunState::PlayerTurn { return state; }
+            ActionCost::Door
+        }
+        PlayerAction::Rest => ActionCost::Rest,
+    };
+    let player = *ecs.fetch::<Entity>();
+    if let Some(energy) = ecs.write_storage::<Energy>().get_mut(player) {
+        energy.spend(cost);
     }
+    RunState::PlayerTurn
 }
 
-pub fn player_input(gs: &mut State, handle: &RaylibHandle) -> RunState {
+///heads for the next level if the player stands on `stairs`, otherwise says so and waits for another command
+fn try_stairs(ecs: &mut World, stairs: TileType) -> RunState {
+    let on_stairs = {
+        let map = ecs.fetch::<Map>();
+        let pos = ecs.fetch::<Point>();
+        map.tiles[map.xy_idx(pos.x, pos.y)] == stairs
+    };
+    match (on_stairs, stairs) {
+        (true, TileType::DownStairs) => RunState::NextLevel,
+        (true, _) => RunState::PreviousLevel,
+        (false, TileType::DownStairs) => {
+            ecs.fetch_mut::<EventLog>().message("There is no way down from here.".to_string());
+            RunState::AwaitingInput
+        }
+        (false, _) => {
+            ecs.fetch_mut::<EventLog>().message("There is no way up from here.".to_string());
+            RunState::AwaitingInput
+        }
+    }
+}
+
+/// Reads the keyboard and maps the pressed key to a player command, if any
+#[cfg(feature = "render")]
+pub fn player_input(handle: &RaylibHandle) -> Option<PlayerAction> {
+    // Stairs, `>` and `<` on most layouts
+    if handle.is_key_pressed(KeyboardKey::KEY_PERIOD) {
+        return Some(PlayerAction::Descend);
+    }
+    if handle.is_key_pressed(KeyboardKey::KEY_COMMA) {
+        return Some(PlayerAction::Ascend);
+    }
+    if handle.is_key_pressed(KeyboardKey::KEY_C) {
+        return Some(PlayerAction::CloseDoor);
+    }
+    if handle.is_key_pressed(KeyboardKey::KEY_SPACE) {
+        return Some(PlayerAction::Rest);
+    }
+
     // Player movement
-    if handle.is_key_pressed(KeyboardKey::KEY_LEFT) || handle.is_key_pressed(KeyboardKey::KEY_H) {
-        try_move_player(-1, 0, &mut gs.ecs);
-        RunState::PlayerTurn
+    let (dx, dy) = if handle.is_key_pressed(KeyboardKey::KEY_LEFT) || handle.is_key_pressed(KeyboardKey::KEY_H) {
+        (-1, 0)
     } else if handle.is_key_pressed(KeyboardKey::KEY_RIGHT)  || handle.is_key_pressed(KeyboardKey::KEY_L) {
-        try_move_player(1, 0, &mut gs.ecs);
-        RunState::PlayerTurn
+        (1, 0)
     } else if handle.is_key_pressed(KeyboardKey::KEY_UP)  || handle.is_key_pressed(KeyboardKey::KEY_K) {
-        try_move_player(0, -1, &mut gs.ecs);
-        RunState::PlayerTurn
+        (0, -1)
     } else if handle.is_key_pressed(KeyboardKey::KEY_DOWN)  || handle.is_key_pressed(KeyboardKey::KEY_J) {
-        try_move_player(0, 1, &mut gs.ecs);
-        RunState::PlayerTurn
+        (0, 1)
     } else if handle.is_key_pressed(KeyboardKey::KEY_Y) {
-        try_move_player(-1, -1, &mut gs.ecs);
-        RunState::PlayerTurn
+        (-1, -1)
     } else if handle.is_key_pressed(KeyboardKey::KEY_U) {
-        try_move_player(1, -1, &mut gs.ecs);
-        RunState::PlayerTurn
+        (1, -1)
     } else if handle.is_key_pressed(KeyboardKey::KEY_N) {
-        try_move_player(1, 1, &mut gs.ecs);
-        RunState::PlayerTurn
+        (1, 1)
     } else if handle.is_key_pressed(KeyboardKey::KEY_B) {
-        try_move_player(-1, 1, &mut gs.ecs);
-        RunState::PlayerTurn
+        (-1, 1)
     } else {
-        RunState::AwaitingInput
+        return None;
+    };
+    if handle.is_key_down(KeyboardKey::KEY_LEFT_SHIFT) || handle.is_key_down(KeyboardKey::KEY_RIGHT_SHIFT) {
+        return Some(PlayerAction::Run { dx, dy });
     }
+    Some(PlayerAction::Move { dx, dy })
 }
-
-
-
-
-
@@ -1,4 +1,6 @@
+use serde::{Serialize, Deserialize};
 
+#[derive(Serialize, Deserialize, Clone)]
 pub struct Rect {
     pub x1 : i32,
     pub x2 : i32,
@@ -0,0 +1,234 @@
+use std::fs;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use specs::prelude::*;
+
+use super::{Position, CombatStats, Monster, AiState, Energy, Map, DoorState, EventLog, Point, PlayerAction, State, SaveError, MapStyle};
+
+/// Where the game writes the replay of the session when quitting.
+pub const REPLAY_PATH : &str = "replay.json";
+
+
+/// Every command the player gave since the game started, plus what's needed to play them back:
+/// the seed the game was started with and a hash of the world when recording stopped.
+/// Kept as a resource while playing, `State::step` appends to it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Recording {
+    pub seed: u64,
+    /// the map style the game was forced to, if any
+    #[serde(default)]
+    pub map_style: Option<MapStyle>,
+    /// width and height of generated levels
+    #[serde(default)]
+    pub map_size: Option<(i32, i32)>,
+    pub actions: Vec<PlayerAction>,
+    pub final_hash: u64,
+}
+
+impl Recording {
+    pub fn new(seed: u64) -> Recording {
+        Recording { seed, map_style: None, map_size: None, actions: Vec::new(), final_hash: 0 }
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Recording, SaveError> {
+        let data = fs::read_to_string(path)?;
+        Ok(serde_json::from_str(&data)?)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), SaveError> {
+        let writer = fs::File::create(path)?;
+        serde_json::to_writer(writer, self)?;
+        Ok(())
+    }
+}
+
+///stops recording: returns what was recorded so far stamped with the current world hash.
+///None if the game isn't being recorded (e.g. it was loaded from a save)
+pub fn finish_recording(ecs: &World) -> Option<Recording> {
+    let mut recording = (*ecs.try_fetch::<Recording>()?).clone();
+    recording.final_hash = world_hash(ecs);
+    Some(recording)
+}
+
+
+///64 bit FNV-1a, used instead of std's hasher because its output is guaranteed not to change between builds.
+///values go in through the methods below as fixed width little endian bytes, never through `Hash`, which writes
+///lengths and integers at the platform's own width and byte order, so a recording made on one machine verifies on any other
+struct Fnv(u64);
+
+impl Fnv {
+    fn new() -> Fnv {
+        Fnv(0xcbf29ce484222325)
+    }
+
+    fn bytes(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= *byte as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    fn u8(&mut self, value: u8) {
+        self.bytes(&[value]);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn i32(&mut self, value: i32) {
+        self.bytes(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, len: usize) {
+        self.bytes(&(len as u64).to_le_bytes());
+    }
+
+    fn point(&mut self, (x, y): (i32, i32)) {
+        self.i32(x);
+        self.i32(y);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes(value.as_bytes());
+    }
+
+    fn door(&mut self, door: Option<DoorState>) {
+        self.u8(match door {
+            None => 0,
+            Some(DoorState::Open) => 1,
+            Some(DoorState::Closed) => 2,
+            Some(DoorState::Locked) => 3,
+        });
+    }
+
+    fn ai_state(&mut self, state: AiState) {
+        match state {
+            AiState::Idle => self.u8(0),
+            AiState::Asleep => self.u8(1),
+            AiState::Wander { x, y } => { self.u8(2); self.point((x, y)); }
+            AiState::Chase => self.u8(3),
+            AiState::Search { turns } => { self.u8(4); self.i32(turns); }
+            AiState::Flee => self.u8(5),
+            AiState::ReturnHome => self.u8(6),
+            AiState::Follow => self.u8(7),
+        }
+    }
+}
+
+///fingerprint of the parts of the world a replay has to reproduce: the level and map, where everything is,
+///everyone's health and energy, what monsters remember and the event log
+pub fn world_hash(ecs: &World) -> u64 {
+    let mut hasher = Fnv::new();
+
+    let map = ecs.fetch::<Map>();
+    hasher.i32(map.depth);
+    hasher.len(map.revealed_tiles.len());
+    for revealed in map.revealed_tiles.iter() {
+        hasher.u8(*revealed as u8);
+    }
+    hasher.len(map.doors.len());
+    for door in map.doors.iter() {
+        hasher.door(*door);
+    }
+    let player_pos = ecs.fetch::<Point>();
+    hasher.point((player_pos.x, player_pos.y));
+
+    let entities = ecs.entities();
+    let positions = ecs.read_storage::<Position>();
+    let stats = ecs.read_storage::<CombatStats>();
+    let monsters = ecs.read_storage::<Monster>();
+    let energies = ecs.read_storage::<Energy>();
+    for (entity, pos) in (&entities, &positions).join() {
+        hasher.u32(entity.id());
+        hasher.point((pos.x, pos.y));
+        if let Some(stats) = stats.get(entity) {
+            hasher.i32(stats.hp);
+            hasher.i32(stats.max_hp);
+        }
+        if let Some(energy) = energies.get(entity) {
+            hasher.i32(energy.speed);
+            hasher.i32(energy.energy);
+        }
+        if let Some(monster) = monsters.get(entity) {
+            hasher.ai_state(monster.state);
+            hasher.point(monster.known_player_location);
+            match monster.home {
+                Some(home) => { hasher.u8(1); hasher.point(home); }
+                None => hasher.u8(0),
+            }
+        }
+    }
+
+    let log = ecs.fetch::<EventLog>();
+    hasher.len(log.log.len());
+    for message in log.log.iter() {
+        hasher.str(message);
+    }
+    hasher.0
+}
+
+
+/// Hands out the commands of a recording one at a time, for driving the turn loop
+/// at whatever pace the caller likes.
+pub struct Replay {
+    recording: Recording,
+    next: usize,
+}
+
+impl Replay {
+    pub fn new(recording: Recording) -> Replay {
+        Replay { recording, next: 0 }
+    }
+
+    ///the seed the game has to be built with for the replay to line up
+    pub fn seed(&self) -> u64 {
+        self.recording.seed
+    }
+
+    ///the map style the game has to be built with, if it was forced to one
+    pub fn map_style(&self) -> Option<MapStyle> {
+        self.recording.map_style
+    }
+
+    ///the width and height levels have to be generated at, None for the default
+    pub fn map_size(&self) -> Option<(i32, i32)> {
+        self.recording.map_size
+    }
+
+    pub fn next_action(&mut self) -> Option<PlayerAction> {
+        let action = self.recording.actions.get(self.next).copied();
+        if action.is_some() {
+            self.next += 1;
+        }
+        action
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.next >= self.recording.actions.len()
+    }
+
+    ///(commands played, total commands)
+    pub fn progress(&self) -> (usize, usize) {
+        (self.next, self.recording.actions.len())
+    }
+
+    ///checks the world ended up where it did when recorded, returns (expected, actual) hashes if not
+    pub fn verify(&self, ecs: &World) -> Result<(), (u64, u64)> {
+        let actual = world_hash(ecs);
+        if actual == self.recording.final_hash {
+            Ok(())
+        } else {
+            Err((self.recording.final_hash, actual))
+        }
+    }
+
+    ///plays the remaining commands as fast as possible, without a window
+    pub fn run_headless(&mut self, gs: &mut State) {
+        while let Some(action) = self.next_action() {
+            gs.play_turn(action);
+        }
+    }
+}
@@ -0,0 +1,69 @@
+use rand::{Error, RngCore, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+
+/// The one source of randomness for the game, stored as an ECS resource.
+/// Everything random (map generation, spawning, combat) draws from this so that
+/// the same seed always plays out the same way.
+pub struct GameRng {
+    seed: u64,
+    rng: ChaCha8Rng,
+}
+
+impl GameRng {
+    ///creates a generator from a known seed
+    pub fn seeded(seed: u64) -> GameRng {
+        GameRng { seed, rng: ChaCha8Rng::seed_from_u64(seed) }
+    }
+
+    ///creates a generator with a fresh random seed, use `seed()` to find out which one
+    pub fn from_entropy() -> GameRng {
+        GameRng::seeded(rand::random())
+    }
+
+    ///the seed this generator was created with
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+impl RngCore for GameRng {
+    fn next_u32(&mut self) -> u32 {
+        self.rng.next_u32()
+    }
+
+    fn next_u64(&mut self) -> u64 {
+        self.rng.next_u64()
+    }
+
+    fn fill_bytes(&mut self, dest: &mut [u8]) {
+        self.rng.fill_bytes(dest)
+    }
+
+    fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), Error> {
+        self.rng.try_fill_bytes(dest)
+    }
+}
+
+///what a saved rng looks like on disk: the seed plus how far along the stream it got
+#[derive(Serialize, Deserialize)]
+struct SavedRng {
+    seed: u64,
+    word_pos: u128,
+}
+
+impl Serialize for GameRng {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        SavedRng { seed: self.seed, word_pos: self.rng.get_word_pos() }.serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for GameRng {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<GameRng, D::Error> {
+        let saved = SavedRng::deserialize(deserializer)?;
+        let mut rng = GameRng::seeded(saved.seed);
+        rng.rng.set_word_pos(saved.word_pos);
+        Ok(rng)
+    }
+}
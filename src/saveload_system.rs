@@ -0,0 +1,252 @@
+use std::convert::Infallible;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use serde::{Serialize, Deserialize};
+use specs::prelude::*;
+use specs::saveload::{SimpleMarker, SimpleMarkerAllocator, SerializeComponents, DeserializeComponents};
+
+use super::{Position, OtherLevelPosition, Renderable, Player, Viewshed, Monster, Name, BlocksTile, CombatStats, MovementProfile,
+            Door, Item, Key, InBackpack, LightSource, Energy, Behaviour, ProvidesHealing, Hearing, Faction, Dialogue, SerializeMe, Map, Dungeon, EventLog, GameRng, Point, RunState};
+
+/// Bump this whenever the save layout changes; older or newer saves are refused instead of misread.
+pub const SAVE_VERSION : u32 = 11;
+
+/// Where the game saves to when quitting and looks for a save at startup.
+pub const SAVE_PATH : &str = "savegame.json";
+
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(std::io::Error),
+    Format(serde_json::Error),
+    IncompatibleVersion { found: u32, expected: u32 },
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "could not access save file: {}", e),
+            SaveError::Format(e) => write!(f, "save file is corrupt: {}", e),
+            SaveError::IncompatibleVersion { found, expected } => write!(f,
+                "save file is from an incompatible version (save format {}, this build reads format {})", found, expected),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<std::io::Error> for SaveError {
+    fn from(e: std::io::Error) -> Self { SaveError::Io(e) }
+}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(e: serde_json::Error) -> Self { SaveError::Format(e) }
+}
+
+
+///just the version, read on its own first so an incompatible save is caught before anything else is parsed
+#[derive(Deserialize)]
+struct SaveHeader {
+    version: u32,
+}
+
+///layout of a save file: the resources the world can't live without plus every marked entity
+#[derive(Deserialize)]
+struct SaveGame {
+    map: Map,
+    dungeon: Dungeon,
+    log: EventLog,
+    rng: GameRng,
+    entities: serde_json::Value,
+    more_components: serde_json::Value,
+}
+
+///the same layout as `SaveGame`, borrowing the resources so they can stay in the world while saving
+#[derive(Serialize)]
+struct SaveGameRef<'a> {
+    version: u32,
+    map: &'a Map,
+    dungeon: &'a Dungeon,
+    log: &'a EventLog,
+    rng: &'a GameRng,
+    entities: serde_json::Value,
+    more_components: serde_json::Value,
+}
+
+
+pub fn does_save_exist(path: impl AsRef<Path>) -> bool {
+    path.as_ref().exists()
+}
+
+///writes every entity marked with `SerializeMe` and the map, the levels left behind, log and rng to `path`
+pub fn save_game(ecs: &World, path: impl AsRef<Path>) -> Result<(), SaveError> {
+    let entities = {
+        let data = ( ecs.entities(), ecs.read_storage::<SimpleMarker<SerializeMe>>() );
+        let storages = (
+            ecs.read_storage::<Position>(),
+            ecs.read_storage::<OtherLevelPosition>(),
+            ecs.read_storage::<Renderable>(),
+            ecs.read_storage::<Player>(),
+            ecs.read_storage::<Viewshed>(),
+            ecs.read_storage::<Monster>(),
+            ecs.read_storage::<Name>(),
+            ecs.read_storage::<BlocksTile>(),
+            ecs.read_storage::<CombatStats>(),
+            ecs.read_storage::<MovementProfile>(),
+            ecs.read_storage::<Door>(),
+            ecs.read_storage::<Item>(),
+            ecs.read_storage::<Key>(),
+            ecs.read_storage::<InBackpack>(),
+            ecs.read_storage::<LightSource>(),
+            ecs.read_storage::<Energy>(),
+        );
+        SerializeComponents::<Infallible, SimpleMarker<SerializeMe>>::serialize(
+            &storages, &data.0, &data.1, serde_json::value::Serializer)?
+    };
+    // specs takes at most 16 kinds of component at once, the rest go in a second pass over the same entities
+    let more_components = {
+        let data = ( ecs.entities(), ecs.read_storage::<SimpleMarker<SerializeMe>>() );
+        let storages = (
+            ecs.read_storage::<Behaviour>(),
+            ecs.read_storage::<ProvidesHealing>(),
+            ecs.read_storage::<Hearing>(),
+            ecs.read_storage::<Faction>(),
+            ecs.read_storage::<Dialogue>(),
+        );
+        SerializeComponents::<Infallible, SimpleMarker<SerializeMe>>::serialize(
+            &storages, &data.0, &data.1, serde_json::value::Serializer)?
+    };
+
+    let map = ecs.fetch::<Map>();
+    let dungeon = ecs.fetch::<Dungeon>();
+    let log = ecs.fetch::<EventLog>();
+    let rng = ecs.fetch::<GameRng>();
+
+    let save = SaveGameRef { version: SAVE_VERSION, map: &map, dungeon: &dungeon, log: &log, rng: &rng, entities, more_components };
+    let writer = fs::File::create(path)?;
+    serde_json::to_writer(writer, &save)?;
+    Ok(())
+}
+
+///replaces the whole world with the contents of the save at `path`. on an error the world is left as it was
+pub fn load_game(ecs: &mut World, path: impl AsRef<Path>) -> Result<(), SaveError> {
+    let data = fs::read_to_string(path)?;
+    let header : SaveHeader = serde_json::from_str(&data)?;
+    if header.version != SAVE_VERSION {
+        return Err(SaveError::IncompatibleVersion { found: header.version, expected: SAVE_VERSION });
+    }
+    let save : SaveGame = serde_json::from_str(&data)?;
+
+    // read the entities into a world of their own first, so a broken save leaves the game it was loaded into alone
+    let mut scratch = scratch_world();
+    read_entities(&mut scratch, save.entities.clone(), save.more_components.clone())?;
+    if find_player(&scratch).is_none() {
+        return Err(SaveError::Format(serde::de::Error::custom("save file has no player")));
+    }
+
+    ecs.delete_all();
+    ecs.maintain();
+    read_entities(ecs, save.entities, save.more_components)?;
+
+    let mut map = save.map;
+    map.tile_content = vec![Vec::new(); map.tiles.len()];
+    ecs.insert(map);
+    ecs.insert(save.dungeon);
+    ecs.insert(save.log);
+    ecs.insert(save.rng);
+
+    // the player entity and its position are resources too, rebuild them from the loaded player
+    let (player_entity, player_pos) = find_player(ecs).expect("the scratch world had a player");
+    ecs.insert(player_entity);
+    ecs.insert(player_pos);
+    ecs.insert(RunState::PreRun);
+
+    Ok(())
+}
+
+///a world with nothing but the saved components registered, for trying a save out on
+fn scratch_world() -> World {
+    let mut ecs = World::new();
+    ecs.register::<Position>();
+    ecs.register::<OtherLevelPosition>();
+    ecs.register::<Renderable>();
+    ecs.register::<Player>();
+    ecs.register::<Viewshed>();
+    ecs.register::<Monster>();
+    ecs.register::<Name>();
+    ecs.register::<BlocksTile>();
+    ecs.register::<CombatStats>();
+    ecs.register::<MovementProfile>();
+    ecs.register::<Door>();
+    ecs.register::<Item>();
+    ecs.register::<Key>();
+    ecs.register::<InBackpack>();
+    ecs.register::<LightSource>();
+    ecs.register::<Energy>();
+    ecs.register::<Behaviour>();
+    ecs.register::<ProvidesHealing>();
+    ecs.register::<Hearing>();
+    ecs.register::<Faction>();
+    ecs.register::<Dialogue>();
+    ecs.register::<SimpleMarker<SerializeMe>>();
+    ecs.insert(SimpleMarkerAllocator::<SerializeMe>::new());
+    ecs
+}
+
+///creates the saved entities in `ecs` from both passes of a save
+fn read_entities(ecs: &mut World, entities: serde_json::Value, more_components: serde_json::Value) -> Result<(), SaveError> {
+    let mut markers = ecs.write_storage::<SimpleMarker<SerializeMe>>();
+    let mut allocator = ecs.write_resource::<SimpleMarkerAllocator<SerializeMe>>();
+    let mut storages = (
+        ecs.write_storage::<Position>(),
+        ecs.write_storage::<OtherLevelPosition>(),
+        ecs.write_storage::<Renderable>(),
+        ecs.write_storage::<Player>(),
+        ecs.write_storage::<Viewshed>(),
+        ecs.write_storage::<Monster>(),
+        ecs.write_storage::<Name>(),
+        ecs.write_storage::<BlocksTile>(),
+        ecs.write_storage::<CombatStats>(),
+        ecs.write_storage::<MovementProfile>(),
+        ecs.write_storage::<Door>(),
+        ecs.write_storage::<Item>(),
+        ecs.write_storage::<Key>(),
+        ecs.write_storage::<InBackpack>(),
+        ecs.write_storage::<LightSource>(),
+        ecs.write_storage::<Energy>(),
+    );
+    DeserializeComponents::<Infallible, _>::deserialize(
+        &mut storages, &ecs.entities(), &mut markers, &mut allocator, entities)?;
+
+    // the markers find the entities the first pass made, so these land on the same ones
+    let mut storages = (
+        ecs.write_storage::<Behaviour>(),
+        ecs.write_storage::<ProvidesHealing>(),
+        ecs.write_storage::<Hearing>(),
+        ecs.write_storage::<Faction>(),
+        ecs.write_storage::<Dialogue>(),
+    );
+    DeserializeComponents::<Infallible, _>::deserialize(
+        &mut storages, &ecs.entities(), &mut markers, &mut allocator, more_components)?;
+    Ok(())
+}
+
+///the player entity and where it stands
+fn find_player(ecs: &World) -> Option<(Entity, Point)> {
+    let entities = ecs.entities();
+    let players = ecs.read_storage::<Player>();
+    let positions = ecs.read_storage::<Position>();
+    (&entities, &players, &positions).join()
+        .map(|(entity, _player, pos)| (entity, Point::new(pos.x, pos.y)))
+        .next()
+}
+
+///removes the save at `path`, if there is one
+pub fn delete_save(path: impl AsRef<Path>) -> Result<(), SaveError> {
+    if does_save_exist(&path) {
+        fs::remove_file(path)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,225 @@
+use rand::Rng;
+use specs::prelude::*;
+use specs::saveload::{MarkedBuilder, SimpleMarker};
+
+use super::{Position, Renderable, Player, Viewshed, VisibleTiles, FovAlgorithm, Monster, AiState, Behaviour, Name, BlocksTile, CombatStats, MovementProfile, Door, Item, Key,
+            ProvidesHealing, InBackpack, Hearing, LightSource, Energy, Faction, Dialogue, PLAYER_FACTION, DEFAULT_FACTION,
+            Point, Color, GameRng, SerializeMe, BuiltLevel, TileType, DijkstraMap, DoorState, DOOR_CLOSED_SPRITE, NORMAL_SPEED, TURN_ENERGY};
+
+
+///spawns the player at the given coordinates and returns its entity
+pub fn player(ecs: &mut World, player_x: i32, player_y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position { x: player_x, y: player_y })
+        .with(Renderable {
+            index: 8,
+            color: Color::WHITE,
+        })
+        .with(Player{health : 100})
+        .with(Viewshed{visible_tiles: VisibleTiles::new(), range : 8, dirty: true, algorithm: FovAlgorithm::Symmetric, seen_from: None})
+        .with(Name{name: "Player".to_string()})
+        .with(CombatStats{max_hp: 30, hp: 30, defense: 2, power: 5})
+        .with(Faction{ name: PLAYER_FACTION.to_string() })
+        // ready to act as soon as the game starts
+        .with(Energy{ speed: NORMAL_SPEED, energy: TURN_ENERGY })
+        // a lantern, without it nothing but the tiles right next to the player could be seen
+        .with(LightSource{ color: Color::new(255, 210, 150, 255), range: 6, intensity: 1.0 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+///spawns a random monster at the given coordinates, `i` is used to number its name.
+///monsters get tougher the deeper the `depth` they're spawned at
+pub fn random_monster(ecs: &mut World, x: i32, y: i32, i: usize, depth: i32) -> Entity {
+    let (roll, asleep) = {
+        let mut rng = ecs.write_resource::<GameRng>();
+        (rng.gen_range(0..10), rng.gen_range(0..3) == 0)
+    };
+    let (sprite, name, movement, speed, glow, behaviour, hearing, faction, dialogue) = match roll {
+        // telekinetic, it opens doors without hands. Its eye gives off a faint glow, and it drifts along slowly. No ears to speak of
+        0..=2 => { (13, "beholder".to_string(), MovementProfile::flying().opening_doors(), NORMAL_SPEED * 7 / 10,
+                Some(LightSource{ color: Color::new(190, 120, 255, 255), range: 3, intensity: 0.6 }), None, 3, DEFAULT_FACTION, None) }
+        // does whatever behaviours/goblin.txt says, and carries a potion to drink when things go badly. Sharp eared
+        3..=5 => { (10, "goblin".to_string(), MovementProfile::walking().opening_doors(), NORMAL_SPEED, None,
+                Some(Behaviour{ tree: "goblin".to_string() }), 1, "goblins", None) }
+        // keeps to itself and has a word for the player, the rare one that isn't out for blood
+        9 => { (9, "hermit".to_string(), MovementProfile::walking().opening_doors(), NORMAL_SPEED * 8 / 10, None, None, 2, "hermits",
+                Some(Dialogue{ lines: vec![
+                    "Mind the crabs, they don't care who they pinch.".to_string(),
+                    "The goblins and the crabs have been at it for years.".to_string(),
+                    "I came down here for the quiet.".to_string(),
+                    "Deeper down they get nastier, so I stay up here.".to_string(),
+                ]})) }
+        // scuttles, a good deal quicker than the player, and goes for goblins as readily as for the player
+        _ => { (23, "motherfuckingcrab".to_string(), MovementProfile::amphibious(), NORMAL_SPEED * 13 / 10, None, None, 2, "wildlife", None) }
+    };
+    let carries_potion = behaviour.is_some();
+    let levels_down = depth.max(1) - 1;
+    let hp = 16 + levels_down * 4;
+    let monster = ecs.create_entity()
+        .with(Position{ x, y })
+        .with(Renderable{
+            index: sprite,
+            color: Color::WHITE,
+        })
+        // the same algorithm as the player, so a monster only spots the player when the player could spot it
+        .with(Viewshed{ visible_tiles : VisibleTiles::new(), range: 8, dirty: true, algorithm: FovAlgorithm::Symmetric, seen_from: None })
+        // some are caught napping, only a noise wakes those
+        .with(Monster{ known_player_location : (0,0), state: if asleep { AiState::Asleep } else { AiState::Idle }, home: Some((x, y)) })
+        .with(Hearing{ threshold: hearing })
+        .with(Name{ name: format!("{} #{}", &name, i)})
+        .with(BlocksTile{})
+        .with(CombatStats{ max_hp: hp, hp, defense: 1 + levels_down / 3, power: 4 + levels_down / 2 })
+        .with(movement)
+        .with(Energy::new(speed))
+        .with(Faction{ name: faction.to_string() })
+        .maybe_with(glow)
+        .maybe_with(behaviour)
+        .maybe_with(dialogue)
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build();
+    if carries_potion {
+        healing_potion(ecs, monster);
+    }
+    monster
+}
+
+///spawns the player's companion at the given coordinates, it fights on the player's side and follows it around
+pub fn squire(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position{ x, y })
+        .with(Renderable{
+            index: 5,
+            color: Color::WHITE,
+        })
+        .with(Viewshed{ visible_tiles : VisibleTiles::new(), range: 8, dirty: true, algorithm: FovAlgorithm::Symmetric, seen_from: None })
+        .with(Monster{ known_player_location : (0,0), state: AiState::Follow, home: None })
+        .with(Hearing{ threshold: 1 })
+        .with(Name{ name: "squire".to_string() })
+        .with(BlocksTile{})
+        .with(CombatStats{ max_hp: 20, hp: 20, defense: 1, power: 4 })
+        .with(MovementProfile::walking().opening_doors())
+        .with(Energy::new(NORMAL_SPEED))
+        .with(Faction{ name: PLAYER_FACTION.to_string() })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+///spawns a healing potion in `owner`'s backpack
+pub fn healing_potion(ecs: &mut World, owner: Entity) -> Entity {
+    ecs.create_entity()
+        .with(Name{ name: "healing potion".to_string() })
+        .with(Item{})
+        .with(ProvidesHealing{ heal_amount: 8 })
+        .with(InBackpack{ owner })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+///puts one random monster on each spawn point of a freshly built level
+pub fn spawn_level_monsters(ecs: &mut World, spawn_points: &[Point], depth: i32) {
+    for (i, point) in spawn_points.iter().enumerate() {
+        random_monster(ecs, point.x, point.y, i, depth);
+    }
+}
+
+///spawns a closed door at the given coordinates
+pub fn door(ecs: &mut World, x: i32, y: i32, locked: bool) -> Entity {
+    ecs.create_entity()
+        .with(Position{ x, y })
+        .with(Renderable{
+            index: DOOR_CLOSED_SPRITE,
+            color: Color::WHITE,
+        })
+        .with(Name{ name: if locked { "locked door".to_string() } else { "door".to_string() }})
+        .with(Door{ open: false, locked })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+///spawns a key lying at the given coordinates
+pub fn key(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position{ x, y })
+        .with(Renderable{
+            index: 90,
+            color: Color::GOLD,
+        })
+        .with(Name{ name: "key".to_string() })
+        .with(Item{})
+        .with(Key{})
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+///puts the doors a freshly built level asked for in place, and for every locked one a key somewhere
+///it can be fetched from without going through a locked door
+pub fn spawn_level_doors(ecs: &mut World, level: &mut BuiltLevel) {
+    for (point, locked) in level.doors.iter() {
+        door(ecs, point.x, point.y, *locked);
+        let idx = level.map.xy_idx(point.x, point.y);
+        // the map hears about doors from MapIndexingSystem, but sight is worked out before that first runs
+        level.map.doors[idx] = Some(if *locked { DoorState::Locked } else { DoorState::Closed });
+    }
+
+    let locks = level.doors.iter().filter(|(_, locked)| *locked).count();
+    if locks == 0 { return; }
+    let map = &mut level.map;
+    map.populate_blocked();
+    let start = map.xy_idx(level.start.x, level.start.y);
+    let distances = DijkstraMap::new(&[start], &*map, f32::MAX);
+    let spots : Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| *idx != start && map.tiles[*idx] == TileType::Floor && map.doors[*idx].is_none())
+        .filter(|idx| distances.is_reachable(*idx))
+        .collect();
+    if spots.is_empty() { return; }
+    for _ in 0..locks {
+        let idx = {
+            let mut rng = ecs.write_resource::<GameRng>();
+            spots[rng.gen_range(0..spots.len())]
+        };
+        key(ecs, idx as i32 % map.width, idx as i32 / map.width);
+    }
+}
+
+///spawns a torch burning at the given coordinates
+pub fn torch(ecs: &mut World, x: i32, y: i32) -> Entity {
+    ecs.create_entity()
+        .with(Position{ x, y })
+        .with(Renderable{
+            index: 136,
+            color: Color::WHITE,
+        })
+        .with(Name{ name: "torch".to_string() })
+        .with(LightSource{ color: Color::new(255, 170, 80, 255), range: 7, intensity: 1.0 })
+        .marked::<SimpleMarker<SerializeMe>>()
+        .build()
+}
+
+///puts torches on a freshly built level, against the walls, about one for every so many floor tiles
+pub fn spawn_level_torches(ecs: &mut World, level: &BuiltLevel) {
+    const TILES_PER_TORCH : usize = 120;
+
+    let map = &level.map;
+    let floor = (0..map.tiles.len()).filter(|idx| map.tiles[*idx] == TileType::Floor).count();
+    let against_wall : Vec<usize> = (0..map.tiles.len())
+        .filter(|idx| map.tiles[*idx] == TileType::Floor && map.doors[*idx].is_none())
+        .filter(|idx| {
+            let (x, y) = (*idx as i32 % map.width, *idx as i32 / map.width);
+            [(0, -1), (0, 1), (-1, 0), (1, 0)].iter()
+                .any(|(dx, dy)| map.in_bounds(x + dx, y + dy) && map.tiles[map.xy_idx(x + dx, y + dy)] == TileType::Wall)
+        })
+        .collect();
+    if against_wall.is_empty() { return; }
+
+    let mut placed : Vec<usize> = Vec::new();
+    for _ in 0..(floor / TILES_PER_TORCH).max(1) {
+        let idx = {
+            let mut rng = ecs.write_resource::<GameRng>();
+            against_wall[rng.gen_range(0..against_wall.len())]
+        };
+        if placed.contains(&idx) { continue; }
+        placed.push(idx);
+        torch(ecs, idx as i32 % map.width, idx as i32 / map.width);
+    }
+}
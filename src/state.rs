@@ -1,20 +1,22 @@
 use super::*;
 
 
-#[derive(PartialEq, Copy, Clone)]
-pub enum RunState { AwaitingInput, PreRun, PlayerTurn, MonsterTurn }
+#[derive(PartialEq, Copy, Clone, Debug)]
+pub enum RunState { AwaitingInput, PreRun, PlayerTurn, MonsterTurn, NextLevel, PreviousLevel }
 
 
 
 
 pub struct State {
     pub ecs: World,
+    /// game-specific systems, run every turn after the engine's own systems
+    pub systems: Vec<Box<dyn for<'a> RunNow<'a>>>,
 }
 
 impl State {
-    pub fn tick(&mut self, handle : &mut RaylibHandle, thread: RaylibThread, tileset : &Vec<Texture2D>) {
-
-
+    /// Advances the run state machine by one step without touching any window or input device.
+    /// `action` is only consumed while the game is awaiting input; returns the new run state.
+    pub fn step(&mut self, action: Option<PlayerAction>) -> RunState {
         let mut newrunstate;
         {
             let runstate = self.ecs.fetch::<RunState>();
@@ -27,15 +29,34 @@ impl State {
                 newrunstate = RunState::AwaitingInput;
             }
             RunState::AwaitingInput => {
-                newrunstate = player_input(self, handle);
+                if let Some(action) = action {
+                    if let Some(mut recording) = self.ecs.try_fetch_mut::<Recording>() {
+                        recording.actions.push(action);
+                    }
+                    newrunstate = apply_player_action(&mut self.ecs, action);
+                }
             }
             RunState::PlayerTurn => {
                 self.run_systems();
                 newrunstate = RunState::MonsterTurn;
             }
             RunState::MonsterTurn => {
-                self.run_systems();
-                newrunstate = RunState::AwaitingInput;
+                // monsters take their turns one at a time, the one with the most energy first, until the player is due again
+                if next_turn(&mut self.ecs) {
+                    newrunstate = RunState::AwaitingInput;
+                } else {
+                    self.run_systems();
+                }
+            }
+            RunState::NextLevel => {
+                let depth = self.ecs.fetch::<Map>().depth;
+                change_level(&mut self.ecs, depth + 1);
+                newrunstate = RunState::PreRun;
+            }
+            RunState::PreviousLevel => {
+                let depth = self.ecs.fetch::<Map>().depth;
+                change_level(&mut self.ecs, depth - 1);
+                newrunstate = RunState::PreRun;
             }
         }
         {
@@ -44,33 +65,104 @@ impl State {
         }
         damage_system::delete_the_dead(&mut self.ecs);
 
+        newrunstate
+    }
+
+    /// Plays one whole turn headlessly: feeds `action` to the player and steps until input is awaited again
+    pub fn play_turn(&mut self, action: PlayerAction) {
+        // get past PreRun so the action isn't dropped
+        self.finish_turn();
+        self.step(Some(action));
+        self.finish_turn();
+    }
+
+    /// Steps without input until the game is waiting for the player again
+    pub fn finish_turn(&mut self) {
+        while *self.ecs.fetch::<RunState>() != RunState::AwaitingInput {
+            self.step(None);
+        }
+    }
+
+    #[cfg(feature = "render")]
+    pub fn tick(&mut self, handle : &mut RaylibHandle, thread: RaylibThread, tileset : &[Texture2D]) {
+        let action = player_input(handle);
+        self.step(action);
+        self.draw(handle, thread, tileset);
+    }
 
-        
+    #[cfg(feature = "render")]
+    pub fn draw(&self, handle : &mut RaylibHandle, thread: RaylibThread, tileset : &[Texture2D]) {
+        {
+            let mut camera = self.ecs.write_resource::<Camera>();
+            camera.fit_screen(handle.get_screen_width(), handle.get_screen_height());
+            camera.follow(*self.ecs.fetch::<Point>(), &self.ecs.fetch::<Map>());
+        }
+        let mouse = handle.get_mouse_position();
         let mut draw = handle.begin_drawing(&thread);
 
-        
+
         let positions = self.ecs.read_storage::<Position>();
         let renderables = self.ecs.read_storage::<Renderable>();
-        
+
         let map = self.ecs.fetch::<Map>();
+        let camera = self.ecs.fetch::<Camera>();
 
         draw.clear_background(Color::BLACK);
-        
 
-        draw_map(&self.ecs, &mut draw, &tileset);
+
+        draw_map(&self.ecs, &mut draw, tileset);
 
         for (pos, render) in (&positions, &renderables).join() {
             let idx = map.xy_idx(pos.x, pos.y);
-            if map.visible_tiles[idx] {
-                draw.draw_texture_ex(&tileset[render.index as usize],Vector2::new((pos.x * TILE_SIZE) as f32 * SCALE ,(pos.y * TILE_SIZE) as f32 * SCALE),0.0,SCALE,Color::WHITE);
+            if map.visible_tiles[idx] && camera.contains(pos.x, pos.y) {
+                let (screen_x, screen_y) = camera.map_to_screen(pos.x, pos.y);
+                draw.draw_texture_ex(&tileset[render.index as usize],Vector2::new(screen_x, screen_y),0.0,SCALE,Color::WHITE);
             }
         }
+        self.draw_tooltip(&mut draw, mouse);
         draw_log(&self.ecs, &mut draw);
+
+        let seed = self.ecs.fetch::<GameRng>().seed();
+        let bottom = draw.get_screen_height() - 10;
+        draw.draw_text(&format!("depth {}  seed {}", map.depth, seed), 0, bottom, 8, Color::GRAY);
+    }
+
+    ///outlines the tile under the mouse and names whatever the player can see standing on it, or else the ground itself
+    #[cfg(feature = "render")]
+    fn draw_tooltip(&self, draw: &mut RaylibDrawHandle, mouse: Vector2) {
+        let map = self.ecs.fetch::<Map>();
+        let camera = self.ecs.fetch::<Camera>();
+        let Some(tile) = camera.screen_to_map(mouse.x, mouse.y, &map) else { return; };
+
+        let (screen_x, screen_y) = camera.map_to_screen(tile.x, tile.y);
+        let size = Camera::tile_pixels() as i32;
+        draw.draw_rectangle_lines(screen_x as i32, screen_y as i32, size, size, Color::GRAY);
+
+        let idx = map.xy_idx(tile.x, tile.y);
+        if !map.revealed_tiles[idx] { return; }
+        let names = self.ecs.read_storage::<Name>();
+        let mut here : Vec<&str> = Vec::new();
+        if map.visible_tiles[idx] {
+            here.extend(map.tile_content[idx].iter()
+                .filter_map(|entity| names.get(*entity))
+                .map(|name| name.name.as_str()));
+        }
+        // nothing there, or nothing that can be seen from here, so say what the ground is
+        if here.is_empty() {
+            here.push(map.tiles[idx].def().description);
+        }
+        draw.draw_text(&here.join(", "), screen_x as i32 + size + 2, screen_y as i32, 8, Color::WHITE);
     }
 
     fn run_systems(&mut self) {
+        let mut lighting = LightingSystem{};
+        lighting.run_now(&self.ecs);
         let mut vis = VisibilitySystem{};
         vis.run_now(&self.ecs);
+        let mut hearing = HearingSystem{};
+        hearing.run_now(&self.ecs);
+        let mut behaviour = BehaviourSystem{};
+        behaviour.run_now(&self.ecs);
         let mut mov = MonsterAI{};
         mov.run_now(&self.ecs);
         let mut mapindex = MapIndexingSystem{};
@@ -79,6 +171,9 @@ impl State {
         melee.run_now(&self.ecs);
         let mut damage = DamageSystem{};
         damage.run_now(&self.ecs);
+        for system in self.systems.iter_mut() {
+            system.run_now(&self.ecs);
+        }
         self.ecs.maintain();
     }
 }
@@ -0,0 +1,139 @@
+use std::collections::HashSet;
+
+use serde::{Serialize, Deserialize};
+
+use super::{Point, Algorithm2D, VisibleTiles, field_of_view_into};
+
+
+/// How a `Viewshed` works out what it can see
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Default, Serialize, Deserialize)]
+pub enum FovAlgorithm {
+    /// the recursive eight octant scanner in `field_of_view_set`. Quick, but seeing a tile
+    /// doesn't always mean being seen from it
+    #[default]
+    Scanning,
+    /// `symmetric_field_of_view_set`: if a floor tile can see another, that one can see it back
+    Symmetric,
+}
+
+impl FovAlgorithm {
+    ///every tile within `range` of `center` this algorithm can see, walls included
+    pub fn field_of_view_set(self, center: Point, range: i32, map: &dyn Algorithm2D) -> HashSet<Point> {
+        let mut visible = VisibleTiles::new();
+        self.field_of_view_into(center, range, map, &mut visible);
+        visible.iter().collect()
+    }
+
+    ///like `field_of_view_set`, but fills `visible` instead, reusing its memory
+    pub fn field_of_view_into(self, center: Point, range: i32, map: &dyn Algorithm2D, visible: &mut VisibleTiles) {
+        match self {
+            FovAlgorithm::Scanning => field_of_view_into(center, range, map, visible),
+            FovAlgorithm::Symmetric => symmetric_field_of_view_into(center, range, map, visible),
+        }
+    }
+}
+
+
+/// A slope as an exact fraction, `num / den` with `den` always positive
+#[derive(Copy, Clone, Debug)]
+struct Slope {
+    num: i32,
+    den: i32,
+}
+
+impl Slope {
+    ///the slope through the edge of column `col` nearest the centre line, `depth` rows out
+    fn through_edge(depth: i32, col: i32) -> Slope {
+        Slope { num: 2 * col - 1, den: 2 * depth }
+    }
+}
+
+/// A row of tiles `depth` steps out from the centre, scanned between two slopes
+#[derive(Copy, Clone, Debug)]
+struct Row {
+    depth: i32,
+    start: Slope,
+    end: Slope,
+}
+
+impl Row {
+    ///first column of the row, a start slope falling exactly between two tiles takes the one further along
+    fn min_col(&self) -> i32 {
+        // floor(depth * start + 0.5)
+        (2 * self.depth * self.start.num + self.start.den).div_euclid(2 * self.start.den)
+    }
+
+    ///last column of the row, an end slope falling exactly between two tiles takes the one further back
+    fn max_col(&self) -> i32 {
+        // ceil(depth * end - 0.5)
+        -(-2 * self.depth * self.end.num + self.end.den).div_euclid(2 * self.end.den)
+    }
+
+    fn next(&self) -> Row {
+        Row { depth: self.depth + 1, ..*self }
+    }
+
+    ///true if the centre of the floor tile in `col` lies between the row's slopes, which is what keeps the
+    ///whole thing symmetric
+    fn sees_centre(&self, col: i32) -> bool {
+        col * self.start.den >= self.depth * self.start.num && col * self.end.den <= self.depth * self.end.num
+    }
+}
+
+///field of view by symmetric shadowcasting: a floor tile is seen if a line from the centre of the
+///viewer's tile reaches its centre unblocked, walls are seen if any part of them is lit. Unlike
+///`field_of_view_set` it never recurses, rows still to be scanned go on a stack instead
+pub fn symmetric_field_of_view_set(center: Point, range: i32, map: &dyn Algorithm2D) -> HashSet<Point> {
+    FovAlgorithm::Symmetric.field_of_view_set(center, range, map)
+}
+
+///`symmetric_field_of_view_set` into `visible_points`, which is emptied first
+pub fn symmetric_field_of_view_into(center: Point, range: i32, map: &dyn Algorithm2D, visible_points: &mut VisibleTiles) {
+    let dimensions = map.dimensions();
+    visible_points.reset(dimensions.x, dimensions.y);
+    if !map.in_bounds(center) {
+        return;
+    }
+    visible_points.insert(center);
+
+    // measured from the near corner of the tile the same way as `field_of_view_set`, so both reach as far
+    let r2 = (range * range) as f32;
+    let in_range = |point: Point| {
+        let dx = (point.x - center.x).abs() as f32 - 0.5;
+        let dy = (point.y - center.y).abs() as f32 - 0.5;
+        dx * dx + dy * dy <= r2
+    };
+    // each quarter is scanned as if looking north: `depth` rows out, `col` across.
+    // (depth, col) turns into a map offset of (col * a + depth * b, col * c + depth * d)
+    const QUARTERS: [(i32, i32, i32, i32); 4] = [ (1, 0, 0, -1), (0, 1, 1, 0), (1, 0, 0, 1), (0, -1, 1, 0) ];
+    let mut rows : Vec<Row> = Vec::new();
+
+    for (a, b, c, d) in QUARTERS {
+        let tile = |depth: i32, col: i32| Point::new(center.x + col * a + depth * b, center.y + col * c + depth * d);
+        let is_wall = |point: Point| !map.in_bounds(point) || map.is_opaque(map.point2d_to_index(point));
+
+        rows.push(Row { depth: 1, start: Slope { num: -1, den: 1 }, end: Slope { num: 1, den: 1 } });
+        while let Some(mut row) = rows.pop() {
+            if row.depth > range { continue; }
+            let mut previous_wall : Option<bool> = None;
+            for col in row.min_col() ..= row.max_col() {
+                let point = tile(row.depth, col);
+                let wall = is_wall(point);
+                if (wall || row.sees_centre(col)) && in_range(point) {
+                    visible_points.insert(point);
+                }
+                match previous_wall {
+                    // coming out from behind a wall, the lit part of the row starts here
+                    Some(true) if !wall => row.start = Slope::through_edge(row.depth, col),
+                    // a wall starts, the floor up to it lights the next row
+                    Some(false) if wall => rows.push(Row { end: Slope::through_edge(row.depth, col), ..row.next() }),
+                    _ => {}
+                }
+                previous_wall = Some(wall);
+            }
+            if previous_wall == Some(false) {
+                rows.push(row.next());
+            }
+        }
+    }
+}
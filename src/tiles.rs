@@ -0,0 +1,100 @@
+use serde::{Serialize, Deserialize};
+
+use super::{Color, MoveCost};
+
+
+#[derive(PartialEq, Eq, Hash, Copy, Clone, Debug, Serialize, Deserialize)]
+pub enum TileType {
+    Wall, Floor, Mud, Water, Rubble, DownStairs, UpStairs, Lava, Grass, Chasm
+}
+
+/// Everything the engine needs to know about a kind of tile. Sight, movement and drawing all look
+/// things up here, so a new tile only needs a `TileType` variant and an entry in `TILES`.
+#[derive(Debug)]
+pub struct TileDef {
+    pub name: &'static str,
+    /// shown when the mouse is over the tile
+    pub description: &'static str,
+    /// blocks sight
+    pub opaque: bool,
+    /// nothing can enter it at all, not even flyers
+    pub solid: bool,
+    /// what stepping onto it costs anything without a `MovementProfile` of its own,
+    /// `Impassable` if it can't be walked on
+    pub move_cost: MoveCost,
+    /// index into the tileset
+    pub sprite: u8,
+    /// the sprite is drawn multiplied by this, white leaves it as it is
+    pub tint: Color,
+}
+
+/// One entry per `TileType`, in the order the variants are declared
+pub const TILES : [TileDef; 10] = [
+    TileDef {
+        name: "wall", description: "Solid rock.",
+        opaque: true, solid: true, move_cost: MoveCost::Impassable,
+        sprite: 1, tint: Color::WHITE,
+    },
+    TileDef {
+        name: "floor", description: "Bare stone floor.",
+        opaque: false, solid: false, move_cost: MoveCost::Cost(1.0),
+        sprite: 17, tint: Color::WHITE,
+    },
+    TileDef {
+        name: "mud", description: "Thick mud, slow going.",
+        opaque: false, solid: false, move_cost: MoveCost::Cost(2.0),
+        sprite: 54, tint: Color::WHITE,
+    },
+    TileDef {
+        name: "water", description: "Deep water.",
+        opaque: false, solid: false, move_cost: MoveCost::Avoid,
+        sprite: 110, tint: Color::WHITE,
+    },
+    TileDef {
+        name: "rubble", description: "Loose rubble.",
+        opaque: false, solid: false, move_cost: MoveCost::Cost(1.5),
+        sprite: 68, tint: Color::WHITE,
+    },
+    TileDef {
+        name: "down stairs", description: "Stairs leading further down.",
+        opaque: false, solid: false, move_cost: MoveCost::Cost(1.0),
+        sprite: 52, tint: Color::WHITE,
+    },
+    TileDef {
+        name: "up stairs", description: "A ladder leading back up.",
+        opaque: false, solid: false, move_cost: MoveCost::Cost(1.0),
+        sprite: 91, tint: Color::WHITE,
+    },
+    TileDef {
+        name: "lava", description: "Glowing molten rock.",
+        opaque: false, solid: false, move_cost: MoveCost::Avoid,
+        sprite: 110, tint: Color::new(255, 90, 30, 255),
+    },
+    TileDef {
+        name: "grass", description: "Pale grass growing in the dark.",
+        opaque: false, solid: false, move_cost: MoveCost::Cost(1.0),
+        sprite: 69, tint: Color::WHITE,
+    },
+    TileDef {
+        name: "chasm", description: "A chasm, too wide to jump.",
+        opaque: false, solid: false, move_cost: MoveCost::Impassable,
+        sprite: 103, tint: Color::WHITE,
+    },
+];
+
+impl TileType {
+    ///the tile's properties
+    pub fn def(self) -> &'static TileDef {
+        &TILES[self as usize]
+    }
+
+    ///how hard the tile is to cross for anything without a `MovementProfile` of its own
+    pub fn move_cost(self) -> MoveCost {
+        self.def().move_cost
+    }
+
+    ///true if something without a `MovementProfile` can walk onto it
+    pub fn walkable(self) -> bool {
+        self.def().move_cost != MoveCost::Impassable
+    }
+}
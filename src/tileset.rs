@@ -0,0 +1,31 @@
+use raylib::prelude::*;
+
+use super::TILE_SIZE;
+
+
+///loads the tile set vector from a specified path
+pub fn load_tile_set(mut rl:RaylibHandle, thread: RaylibThread, path: &str) -> (Image,Vec<Texture2D>,RaylibHandle, RaylibThread) {
+	  let image: Image = Image::load_image(path).unwrap();
+	  let mut tileset: Vec<Texture2D> = Vec::new();
+    
+    let width = image.width()/TILE_SIZE;
+    let height = image.height()/TILE_SIZE;
+
+	  let mut y:u16 = 0;
+	  let mut x:u16 = 0;
+	  //add tiles to tilemap vector
+	  for _i in 0..width*height {
+        if x >(width-1) as u16{
+    	  x = 0;
+    	  y += 1
+        }
+        let mut temp: Image = image.clone();
+        //crops the source image to the current tile that we want to source
+        temp.crop(Rectangle::new( (x*TILE_SIZE as u16) as f32,(y*TILE_SIZE as u16) as f32,TILE_SIZE as f32, TILE_SIZE  as f32));
+        //adds this to the tilemap vector
+        tileset.push(rl.load_texture_from_image(&thread,&temp).unwrap());
+        x += 1;
+	  }
+	  (image, tileset,rl,thread)
+}
+
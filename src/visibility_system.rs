@@ -1,31 +1,39 @@
 use specs::prelude::*;
-use super::{Viewshed, Position, Map, Player};
+use super::{Viewshed, Position, Map, Player, Algorithm2D, FovAlgorithm, FovCache, VisibleTiles};
 use std::collections::HashSet;
 
 pub struct VisibilitySystem {}
 
 impl<'a> System<'a> for VisibilitySystem {
     type SystemData = ( WriteExpect<'a, Map>,
+                        WriteExpect<'a, FovCache>,
                         Entities<'a>,
                         WriteStorage<'a, Viewshed>, 
                         WriteStorage<'a, Position>,
                         ReadStorage<'a, Player>);
 
     fn run(&mut self, data : Self::SystemData) {
-        let (mut map, entities, mut viewshed, pos, player) = data;
+        let (mut map, mut cache, entities, mut viewshed, pos, player) = data;
 
         for (ent,viewshed,pos) in (&entities, &mut viewshed, &pos).join() {
-            if viewshed.dirty {
+            let center = Point::new(pos.x, pos.y);
+            // nothing to do if it hasn't moved and nothing blocking sight has changed since it last looked
+            let seen_from = Some((center, map.revision()));
+            if viewshed.dirty || viewshed.seen_from != seen_from {
                 viewshed.dirty = false;
-                viewshed.visible_tiles.clear();
-                viewshed.visible_tiles = field_of_view(Point::new(pos.x,pos.y), viewshed.range, &*map);
-                viewshed.visible_tiles.retain(|p| p.0 >= 0 && p.0 < map.width && p.1 >= 0 && p.1 < map.height );
-
-                let p : Option<&Player> = player.get(ent);
-                if let Some(p) = p {
-                    for t in map.visible_tiles.iter_mut() { *t = false };
-                    for vis in viewshed.visible_tiles.iter() {
-                        let idx = map.xy_idx(vis.0, vis.1);
+                viewshed.seen_from = seen_from;
+                viewshed.visible_tiles.clone_from(cache.get_or_compute(center, viewshed.range, viewshed.algorithm, &map));
+            }
+
+            // the light changes even when the player stands still, so this is redone every time
+            let p : Option<&Player> = player.get(ent);
+            if p.is_some() {
+                for t in map.visible_tiles.iter_mut() { *t = false };
+                for vis in viewshed.visible_tiles.iter() {
+                    let idx = map.xy_idx(vis.x, vis.y);
+                    // in the dark only what's right next to the player can be made out
+                    let close = Map::distance2d_pythagoras(pos.x, pos.y, vis.x, vis.y) < 1.5;
+                    if close || map.is_lit(idx) {
                         map.revealed_tiles[idx] = true;
                         map.visible_tiles[idx] = true;
                     }
@@ -59,13 +67,14 @@ struct ScanFovData<'a> {
     center: Point,
     dimensions: Point,
     range_2: i32,
-    map: &'a Map,
-    visible_points: &'a mut HashSet<Point>,
+    map: &'a dyn Algorithm2D,
+    visible_points: &'a mut VisibleTiles,
 }
 
+#[allow(non_snake_case)]
 impl ScanFovData<'_> {
     fn is_transparent(&self, idx: usize, point: Point) -> bool {
-        if self.map.in_bounds(point.x, point.y) {
+        if self.map.in_bounds(point) {
             !self.map.is_opaque(idx)
         } else {
             false
@@ -79,7 +88,7 @@ impl ScanFovData<'_> {
     }
 
     fn insert_visible_for_vertical(&mut self, point: Point) -> bool {
-        let idx = self.map.xy_idx(point.x, point.y);
+        let idx = self.map.point2d_to_index(point);
         let mut is_visible = self.is_transparent(idx, point);
 
         if self.distance_to_center(point) <= self.range_2 as f32 {
@@ -93,7 +102,7 @@ impl ScanFovData<'_> {
     }
 
     fn insert_visible_for_horizontal(&mut self, point: Point) -> bool {
-        let idx = self.map.xy_idx(point.x,point.y);
+        let idx = self.map.point2d_to_index(point);
         let mut is_visible = self.is_transparent(idx, point);
 
         if self.distance_to_center(point) <= self.range_2 as f32 {
@@ -128,7 +137,7 @@ impl ScanFovData<'_> {
             end_x = self.dimensions.x - 1;
         }
 
-        let idx = self.map.xy_idx(current.x,current.y);
+        let idx = self.map.point2d_to_index(current);
         let mut last_visible = self.is_transparent(idx, current);
         for current_x in current.x..=end_x {
             current.x = current_x;
@@ -173,7 +182,7 @@ impl ScanFovData<'_> {
             end_x = 0;
         }
 
-        let idx = self.map.xy_idx(current.x,current.y);
+        let idx = self.map.point2d_to_index(current);
         let mut last_visible = self.is_transparent(idx, current);
         while current.x >= end_x {
             let is_visible = self.insert_visible_for_vertical(current);
@@ -217,7 +226,7 @@ impl ScanFovData<'_> {
             end_x = self.dimensions.x - 1;
         }
 
-        let idx = self.map.xy_idx(current.x,current.y);
+        let idx = self.map.point2d_to_index(current);
         let mut last_visible = self.is_transparent(idx, current);
         for current_x in current.x..=end_x {
             current.x = current_x;
@@ -262,7 +271,7 @@ impl ScanFovData<'_> {
             end_x = 0;
         }
 
-        let idx = self.map.xy_idx(current.x,current.y);
+        let idx = self.map.point2d_to_index(current);
         let mut last_visible = self.is_transparent(idx, current);
         while current.x >= end_x {
             let is_visible = self.insert_visible_for_vertical(current);
@@ -306,7 +315,7 @@ impl ScanFovData<'_> {
             end_y = self.dimensions.y - 1;
         }
 
-        let idx = self.map.xy_idx(current.x,current.y);
+        let idx = self.map.point2d_to_index(current);
         let mut last_visible = self.is_transparent(idx, current);
         for current_y in current.y..=end_y {
             current.y = current_y;
@@ -351,7 +360,7 @@ impl ScanFovData<'_> {
             end_y = 0;
         }
 
-        let idx = self.map.xy_idx(current.x,current.y);
+        let idx = self.map.point2d_to_index(current);
         let mut last_visible = self.is_transparent(idx, current);
         while current.y >= end_y {
             let is_visible = self.insert_visible_for_horizontal(current);
@@ -395,7 +404,7 @@ impl ScanFovData<'_> {
             end_y = self.dimensions.y - 1;
         }
 
-        let idx = self.map.xy_idx(current.x,current.y);
+        let idx = self.map.point2d_to_index(current);
         let mut last_visible = self.is_transparent(idx, current);
         for current_y in current.y..=end_y {
             current.y = current_y;
@@ -440,7 +449,7 @@ impl ScanFovData<'_> {
             end_y = 0;
         }
 
-        let idx = self.map.xy_idx(current.x,current.y);
+        let idx = self.map.point2d_to_index(current);
         let mut last_visible = self.is_transparent(idx, current);
         while current.y >= end_y {
             let is_visible = self.insert_visible_for_horizontal(current);
@@ -463,10 +472,16 @@ impl ScanFovData<'_> {
     }
 }
 
-pub fn field_of_view_set(center: Point, range: i32, map: &Map) -> HashSet<Point> {
-    let mut visible_points: HashSet<Point> =
-        HashSet::with_capacity(((range * 2) * (range * 2)) as usize);
+pub fn field_of_view_set(center: Point, range: i32, map: &dyn Algorithm2D) -> HashSet<Point> {
+    FovAlgorithm::Scanning.field_of_view_set(center, range, map)
+}
 
+///`field_of_view_set` into `visible_points`, which is emptied first
+pub fn field_of_view_into(center: Point, range: i32, map: &dyn Algorithm2D, visible_points: &mut VisibleTiles) {
+    let dimensions = map.dimensions();
+    visible_points.reset(dimensions.x, dimensions.y);
+
+    // `VisibleTiles` ignores anything off the map, so nothing here has to check
     visible_points.insert(center);
 
     /* N, NE, E, SE, S, SW, W, NW */
@@ -481,9 +496,7 @@ pub fn field_of_view_set(center: Point, range: i32, map: &Map) -> HashSet<Point>
         let mut current = center;
         loop {
             current = Point::new(current.x + dx, current.y + dy);
-            if current.x < 0 || current.x >= map.width
-                || current.y < 0 || current.y >= map.height
-            {
+            if !map.in_bounds(current) {
                 break;
             }
             let x2 = current.x - center.x;
@@ -494,7 +507,7 @@ pub fn field_of_view_set(center: Point, range: i32, map: &Map) -> HashSet<Point>
                 break;
             }
 
-            let idx = map.xy_idx(current.x, current.y);
+            let idx = map.point2d_to_index(current);
             visible_points.insert(current);
             if map.is_opaque(idx) {
                 break;
@@ -505,10 +518,10 @@ pub fn field_of_view_set(center: Point, range: i32, map: &Map) -> HashSet<Point>
 
     let mut scanner = ScanFovData {
         center,
-        dimensions: Point::new(map.width,map.height),
+        dimensions,
         range_2: r2,
-        map: &map,
-        visible_points: &mut visible_points,
+        map,
+        visible_points,
     };
     if visibility_per_sector[0] {
         scanner.scan_N2NW(1, 0., 1.);
@@ -529,16 +542,10 @@ pub fn field_of_view_set(center: Point, range: i32, map: &Map) -> HashSet<Point>
         scanner.scan_W2SW(1, 0., 1.);
         scanner.scan_W2NW(1, 0., 1.);
     }
-
-    visible_points
-        .iter()
-        .copied()
-        .filter(|p| map.in_bounds(p.x, p.y))
-        .collect()
 }
 
 /// Calculates field-of-view for a map that supports Algorithm2D.
-pub fn field_of_view(start: Point, range: i32, map: &Map) -> Vec<(i32,i32)> {
+pub fn field_of_view(start: Point, range: i32, map: &dyn Algorithm2D) -> Vec<(i32,i32)> {
     let mut visi_points: Vec<(i32,i32)> = Vec::new();
 
     for i in field_of_view_set(start, range, map).into_iter() {
@@ -0,0 +1,115 @@
+//! Parses the shipped behaviour trees and a few small ones, and checks broken trees are turned away
+//! with an error pointing at the right line.
+
+use my_engine::*;
+
+
+///the line a tree that shouldn't parse was turned away at
+fn error_line(text: &str) -> usize {
+    match BehaviourTree::parse("test", text) {
+        Err(BehaviourError::Parse { line, .. }) => line,
+        Err(e) => panic!("expected a parse error, got {}", e),
+        Ok(tree) => panic!("expected a parse error, got {:?}", tree.root),
+    }
+}
+
+#[test]
+fn shipped_trees_load() {
+    let library = BehaviourLibrary::load_dir(BEHAVIOUR_DIR).expect("behaviours should load");
+    let goblin = library.get("goblin").expect("there should be a goblin tree");
+    let BehaviourNode::Selector(branches) = &goblin.root else { panic!("goblin tree should start with a selector") };
+    assert_eq!(branches.len(), 8);
+    assert_eq!(branches.last(), Some(&BehaviourNode::Action(Action::Rest)));
+}
+
+#[test]
+fn parses_a_small_tree() {
+    let text = "\
+name: guard
+# comments and blank lines are skipped anywhere
+---
+selector
+
+    sequence
+        # keep an eye out
+        sees_foe
+        invert
+            hurt 50
+        move_toward foe
+    chance 10
+        call_for_help 6
+    succeed
+        move_toward home
+";
+    let tree = BehaviourTree::parse("file name", text).expect("tree should parse");
+    assert_eq!(tree.name, "guard");
+    assert_eq!(tree.root, BehaviourNode::Selector(vec![
+        BehaviourNode::Sequence(vec![
+            BehaviourNode::Condition(Condition::SeesFoe),
+            BehaviourNode::Invert(Box::new(BehaviourNode::Condition(Condition::Hurt(50)))),
+            BehaviourNode::Action(Action::MoveToward(Target::Foe)),
+        ]),
+        BehaviourNode::Chance(10, Box::new(BehaviourNode::Action(Action::CallForHelp(6)))),
+        BehaviourNode::Succeed(Box::new(BehaviourNode::Action(Action::MoveToward(Target::Home)))),
+    ]));
+}
+
+#[test]
+fn takes_its_name_from_the_file_otherwise() {
+    let tree = BehaviourTree::parse("lazy", "---\nrest\n").expect("tree should parse");
+    assert_eq!(tree.name, "lazy");
+    assert_eq!(tree.root, BehaviourNode::Action(Action::Rest));
+}
+
+#[test]
+fn missing_separator() {
+    assert_eq!(error_line("selector\n    rest\n"), 1);
+    assert_eq!(error_line("name: nothing\n"), 1);
+}
+
+#[test]
+fn unknown_header_key() {
+    assert_eq!(error_line("name: a\nspeed: 3\n---\nrest\n"), 2);
+}
+
+#[test]
+fn bad_indentation() {
+    assert_eq!(error_line("---\nselector\n    rest\n  wander\n"), 4);
+}
+
+#[test]
+fn more_than_one_root() {
+    assert_eq!(error_line("---\nrest\nwander\n"), 3);
+}
+
+#[test]
+fn unknown_names() {
+    assert_eq!(error_line("---\nselector\n    dance\n"), 3);
+    assert_eq!(error_line("---\nsequence\n    sees_dragon\n    attack\n"), 3);
+    assert_eq!(error_line("---\nsequence\n    knows_foe\n    move_toward castle\n"), 4);
+}
+
+#[test]
+fn decorators_need_exactly_one_child() {
+    assert_eq!(error_line("---\nselector\n    invert\n    rest\n"), 3);
+    assert_eq!(error_line("---\nchance 5\n"), 2);
+    assert_eq!(error_line("---\nsucceed\n    rest\n    wander\n"), 2);
+}
+
+#[test]
+fn composites_need_children_and_leaves_have_none() {
+    assert_eq!(error_line("---\nselector\n    sequence\n"), 3);
+    assert_eq!(error_line("---\nselector\n    rest\n        wander\n"), 3);
+}
+
+#[test]
+fn call_for_help_needs_a_radius() {
+    assert_eq!(error_line("---\ncall_for_help\n"), 2);
+    assert_eq!(error_line("---\ncall_for_help lots\n"), 2);
+    assert_eq!(error_line("---\nselector\n    hurt\n    rest\n"), 3);
+}
+
+#[test]
+fn words_after_nodes_that_take_none() {
+    assert_eq!(error_line("---\nselector\n    attack now\n"), 3);
+}
@@ -0,0 +1,34 @@
+//! Generates thousands of levels in every `MapStyle`, prefabs stamped in as in the game, and checks each one
+//! can be walked from its start to every tile, stair and spawn point. The same check as `--check-maps`.
+
+use my_engine::*;
+
+
+/// Seeds checked for each style
+const SEEDS : u64 = 2000;
+
+///every seed's level in `style` that isn't fully connected, and why
+fn unconnected(style: MapStyle, prefabs: &PrefabLibrary) -> Vec<String> {
+    (0..SEEDS)
+        .filter_map(|seed| {
+            let mut rng = GameRng::seeded(seed);
+            let mut level = style.builder().build_map(&mut rng, 2, Point::new(DEFAULT_MAP_WIDTH, DEFAULT_MAP_HEIGHT));
+            prefabs.stamp_prefabs(&mut level, &mut rng, 2);
+            let report = check_connectivity(&level);
+            (!report.is_connected()).then(|| format!("{} seed {}: {}", style, seed, report))
+        })
+        .collect()
+}
+
+#[test]
+fn every_style_is_connected() {
+    let prefabs = PrefabLibrary::load_dir(PREFAB_DIR).expect("prefabs should load");
+    // a thread for each style, any new one is checked as soon as it's in `MapStyle::ALL`
+    let failures : Vec<String> = std::thread::scope(|scope| {
+        let checks : Vec<_> = MapStyle::ALL.iter()
+            .map(|style| scope.spawn(|| unconnected(*style, &prefabs)))
+            .collect();
+        checks.into_iter().flat_map(|check| check.join().expect("check should finish")).collect()
+    });
+    assert!(failures.is_empty(), "{} levels not fully connected:\n{}", failures.len(), failures.join("\n"));
+}
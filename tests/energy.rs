@@ -0,0 +1,55 @@
+//! Checks `next_turn` hands turns out by energy, the most first, whatever order the monsters were made in.
+
+use specs::prelude::*;
+
+use my_engine::*;
+
+
+///a game with nothing on the level but the player, who isn't due for a while
+fn empty_game() -> State {
+    let gs = GameBuilder::new().with_seed(1).without_monsters().build();
+    let player = *gs.ecs.fetch::<Entity>();
+    gs.ecs.write_storage::<Energy>().insert(player, Energy::new(NORMAL_SPEED)).unwrap();
+    gs
+}
+
+fn creature(gs: &mut State, speed: i32, energy: i32) -> Entity {
+    gs.ecs.create_entity()
+        .with(Position{ x: 1, y: 1 })
+        .with(Energy{ speed, energy })
+        .build()
+}
+
+///who `next_turn` picks to go next, who then takes a step
+fn next_up(gs: &mut State) -> Vec<Entity> {
+    assert!(!next_turn(&mut gs.ecs), "a monster should be due before the player");
+    let entities = gs.ecs.entities();
+    let mut turns = gs.ecs.write_storage::<MyTurn>();
+    let mut energies = gs.ecs.write_storage::<Energy>();
+    let up : Vec<Entity> = (&entities, &turns).join().map(|(entity, _)| entity).collect();
+    for entity in up.iter() {
+        turns.remove(*entity);
+        energies.get_mut(*entity).unwrap().spend(ActionCost::Move);
+    }
+    up
+}
+
+#[test]
+fn most_energy_goes_first() {
+    let mut gs = empty_game();
+    let slow = creature(&mut gs, NORMAL_SPEED / 2, TURN_ENERGY);
+    let fast = creature(&mut gs, NORMAL_SPEED * 2, TURN_ENERGY * 19 / 10);
+    assert!(fast.id() > slow.id());
+
+    assert_eq!(next_up(&mut gs), vec![fast]);
+    assert_eq!(next_up(&mut gs), vec![slow]);
+}
+
+#[test]
+fn ties_go_together() {
+    let mut gs = empty_game();
+    let first = creature(&mut gs, NORMAL_SPEED, TURN_ENERGY);
+    let second = creature(&mut gs, NORMAL_SPEED, TURN_ENERGY);
+
+    assert_eq!(next_up(&mut gs), vec![first, second]);
+}
@@ -0,0 +1,170 @@
+//! Compares `FovAlgorithm::Symmetric` with the scanning field of view on wall layouts that are
+//! known to trip shadowcasters up.
+
+use std::collections::HashSet;
+
+use my_engine::*;
+
+
+/// A bare map drawn in ASCII, `#` walls and anything else floor
+struct Grid {
+    width: i32,
+    height: i32,
+    walls: Vec<bool>,
+}
+
+impl Grid {
+    fn parse(rows: &[&str]) -> Grid {
+        Grid {
+            width: rows[0].len() as i32,
+            height: rows.len() as i32,
+            walls: rows.iter().flat_map(|row| row.chars().map(|c| c == '#')).collect(),
+        }
+    }
+}
+
+impl BaseMap for Grid {
+    fn is_opaque(&self, idx: usize) -> bool { self.walls[idx] }
+    fn get_available_exits(&self, _idx: usize) -> Vec<(usize, f32)> { Vec::new() }
+    fn get_pathing_distance(&self, _idx1: usize, _idx2: usize) -> f32 { 0.0 }
+}
+
+impl Algorithm2D for Grid {
+    fn dimensions(&self) -> Point { Point::new(self.width, self.height) }
+}
+
+
+const RANGE : i32 = 8;
+
+const PILLARS : [&str; 11] = [
+    "...................",
+    "...................",
+    "...#.....#.....#...",
+    "...................",
+    "...................",
+    "......#.....#......",
+    "...................",
+    "...................",
+    "...#.....#.....#...",
+    "...................",
+    "...................",
+];
+
+const DIAGONAL : [&str; 7] = [
+    "#########",
+    "#...#...#",
+    "#...#...#",
+    "#..#....#",
+    "#.#.....#",
+    "##......#",
+    "#########",
+];
+
+fn floors(map: &dyn Algorithm2D) -> Vec<Point> {
+    let size = map.dimensions();
+    (0..size.y).flat_map(|y| (0..size.x).map(move |x| Point::new(x, y)))
+        .filter(|p| !map.is_opaque(map.point2d_to_index(*p)))
+        .collect()
+}
+
+///pairs of floor tiles where `a` sees `b` but `b` doesn't see `a`
+fn asymmetric_pairs(map: &dyn Algorithm2D, algorithm: FovAlgorithm) -> Vec<(Point, Point)> {
+    let floors = floors(map);
+    let seen : Vec<HashSet<Point>> = floors.iter().map(|p| algorithm.field_of_view_set(*p, RANGE, map)).collect();
+    let mut pairs = Vec::new();
+    for (i, a) in floors.iter().enumerate() {
+        for (j, b) in floors.iter().enumerate() {
+            if seen[i].contains(b) && !seen[j].contains(a) {
+                pairs.push((*a, *b));
+            }
+        }
+    }
+    pairs
+}
+
+fn both(center: Point, map: &dyn Algorithm2D) -> [HashSet<Point>; 2] {
+    [FovAlgorithm::Scanning.field_of_view_set(center, RANGE, map), FovAlgorithm::Symmetric.field_of_view_set(center, RANGE, map)]
+}
+
+
+#[test]
+fn symmetric_between_pillars() {
+    assert_eq!(asymmetric_pairs(&Grid::parse(&PILLARS), FovAlgorithm::Symmetric), vec![]);
+}
+
+#[test]
+fn scanning_is_not_symmetric_between_pillars() {
+    assert!(!asymmetric_pairs(&Grid::parse(&PILLARS), FovAlgorithm::Scanning).is_empty());
+}
+
+#[test]
+fn symmetric_along_a_diagonal_wall() {
+    assert_eq!(asymmetric_pairs(&Grid::parse(&DIAGONAL), FovAlgorithm::Symmetric), vec![]);
+}
+
+#[test]
+fn symmetric_on_generated_levels() {
+    for seed in 0..3 {
+        let map = Map::new_map_rooms_and_corridors(&mut GameRng::seeded(seed), 1, 40, 30);
+        assert_eq!(asymmetric_pairs(&map, FovAlgorithm::Symmetric), vec![], "rooms, seed {}", seed);
+    }
+    let cave = MapStyle::Caves.builder().build_map(&mut GameRng::seeded(4), 2, Point::new(40, 30)).map;
+    assert_eq!(asymmetric_pairs(&cave, FovAlgorithm::Symmetric), vec![], "caves");
+}
+
+#[test]
+fn open_room_looks_the_same_either_way() {
+    let room = Grid::parse(&["....................."; 21]);
+    let center = Point::new(10, 10);
+    let [scanning, symmetric] = both(center, &room);
+    assert_eq!(scanning, symmetric);
+    // everything a step short of the range is seen, nothing past it
+    for p in floors(&room) {
+        let distance = (((p.x - center.x).pow(2) + (p.y - center.y).pow(2)) as f32).sqrt();
+        if distance <= (RANGE - 1) as f32 { assert!(symmetric.contains(&p), "{:?}", p); }
+        if distance > (RANGE + 1) as f32 { assert!(!symmetric.contains(&p), "{:?}", p); }
+    }
+}
+
+#[test]
+fn walls_stop_sight() {
+    let room = Grid::parse(&[
+        ".........",
+        "....@....",
+        ".........",
+        "#########",
+        ".........",
+    ]);
+    for seen in both(Point::new(4, 1), &room) {
+        assert!((0..9).all(|x| seen.contains(&Point::new(x, 3))), "the wall itself is seen");
+        assert!((0..9).all(|x| !seen.contains(&Point::new(x, 4))), "nothing behind it is");
+    }
+}
+
+#[test]
+fn down_a_corridor() {
+    let corridor = Grid::parse(&[
+        "###########",
+        "...........",
+        "###########",
+    ]);
+    for seen in both(Point::new(0, 1), &corridor) {
+        assert!((0..=RANGE).all(|x| seen.contains(&Point::new(x, 1))));
+    }
+}
+
+#[test]
+fn pillar_hides_what_is_behind_it() {
+    let room = Grid::parse(&[
+        ".......",
+        ".......",
+        "...#...",
+        ".......",
+        ".......",
+    ]);
+    for seen in both(Point::new(1, 2), &room) {
+        assert!(seen.contains(&Point::new(3, 2)));
+        assert!(!seen.contains(&Point::new(4, 2)));
+        assert!(!seen.contains(&Point::new(5, 2)));
+    }
+}
@@ -0,0 +1,74 @@
+//! Puts creatures that won't attack the player on the far side of it in a corridor one tile wide, and checks
+//! none of them ever ends up standing on the player's tile on the way past.
+
+use specs::prelude::*;
+
+use my_engine::*;
+
+
+/// Where the player stands, halfway along the corridor
+const PLAYER : (i32, i32) = (11, 5);
+
+///a game on nothing but a corridor along y = 5, the player in the middle of it
+fn corridor_game() -> State {
+    let mut map = Map::new(1, 30, 11);
+    map.rooms.push(Rect::new(1, 4, 20, 1));
+    map.apply_room_to_map(&map.rooms[0].clone());
+    let gs = GameBuilder::new().with_seed(1).with_map(map).without_monsters().build();
+    let player = *gs.ecs.fetch::<Entity>();
+    *gs.ecs.write_storage::<Position>().get_mut(player).unwrap() = Position{ x: PLAYER.0, y: PLAYER.1 };
+    *gs.ecs.write_resource::<Point>() = Point::new(PLAYER.0, PLAYER.1);
+    gs
+}
+
+///a hermit, which the player is neutral to, next to the player and in `state`
+fn hermit(gs: &mut State, state: AiState, home: (i32, i32)) -> Entity {
+    gs.ecs.create_entity()
+        .with(Position{ x: PLAYER.0 + 1, y: PLAYER.1 })
+        .with(Viewshed{ visible_tiles: VisibleTiles::new(), range: 8, dirty: true, algorithm: FovAlgorithm::Symmetric, seen_from: None })
+        .with(Monster{ known_player_location: (0, 0), state, home: Some(home) })
+        .with(Name{ name: "hermit".to_string() })
+        .with(BlocksTile{})
+        .with(CombatStats{ max_hp: 16, hp: 16, defense: 1, power: 4 })
+        .with(MovementProfile::walking())
+        .with(Energy::new(NORMAL_SPEED))
+        .with(Faction{ name: "hermits".to_string() })
+        .build()
+}
+
+///rests for a while, checking `creature` never steps onto the player
+fn never_walks_into_the_player(gs: &mut State, creature: Entity) {
+    for turn in 0..30 {
+        gs.play_turn(PlayerAction::Rest);
+        let positions = gs.ecs.read_storage::<Position>();
+        let pos = positions.get(creature).expect("it should still be around");
+        assert_ne!((pos.x, pos.y), PLAYER, "turn {}", turn);
+    }
+}
+
+#[test]
+fn returning_home_past_the_player() {
+    let mut gs = corridor_game();
+    let hermit = hermit(&mut gs, AiState::ReturnHome, (4, 5));
+    never_walks_into_the_player(&mut gs, hermit);
+}
+
+#[test]
+fn wandering_past_the_player() {
+    let mut gs = corridor_game();
+    let hermit = hermit(&mut gs, AiState::Wander{ x: 4, y: 5 }, (PLAYER.0 + 1, PLAYER.1));
+    never_walks_into_the_player(&mut gs, hermit);
+}
+
+#[test]
+fn following_the_player() {
+    let mut gs = corridor_game();
+    let squire = squire(&mut gs.ecs, PLAYER.0 + 1, PLAYER.1);
+    for turn in 0..30 {
+        gs.play_turn(PlayerAction::Move{ dx: if turn < 15 { -1 } else { 1 }, dy: 0 });
+        let player = *gs.ecs.fetch::<Point>();
+        let positions = gs.ecs.read_storage::<Position>();
+        let pos = positions.get(squire).expect("the squire should still be around");
+        assert_ne!((pos.x, pos.y), (player.x, player.y), "turn {}", turn);
+    }
+}
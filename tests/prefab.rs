@@ -0,0 +1,125 @@
+//! Parses the shipped prefabs and a few small ones, checks broken prefabs are turned away with an error
+//! pointing at the right line, and that turning and flipping a prefab moves its cells where they belong.
+
+use my_engine::*;
+
+
+///the line a prefab that shouldn't parse was turned away at
+fn error_line(text: &str) -> usize {
+    match Prefab::parse("test", text) {
+        Err(PrefabError::Parse { line, .. }) => line,
+        Err(e) => panic!("expected a parse error, got {}", e),
+        Ok(prefab) => panic!("expected a parse error, got {:?}", prefab.cells),
+    }
+}
+
+///the prefab's rows written out again
+fn rows(prefab: &Prefab) -> Vec<String> {
+    prefab.cells.iter().map(|row| row.iter().collect()).collect()
+}
+
+/// A room that looks different every way it's turned or flipped
+const LOPSIDED : &str = "\
+---
+####
+#M.+
+#..#
+##~#
+#:%#
+";
+
+fn lopsided() -> Prefab {
+    Prefab::parse("lopsided", LOPSIDED).expect("prefab should parse")
+}
+
+#[test]
+fn shipped_prefabs_load() {
+    let library = PrefabLibrary::load_dir(PREFAB_DIR).expect("prefabs should load");
+    assert_eq!(library.prefabs.len(), 4);
+    for prefab in library.prefabs.iter() {
+        assert_eq!(prefab.cells.len() as i32, prefab.height, "{}", prefab.name);
+        assert!(prefab.cells.iter().all(|row| row.len() as i32 == prefab.width), "{}", prefab.name);
+    }
+    let arena = library.prefabs.iter().find(|prefab| prefab.name == "boss arena").expect("there should be a boss arena");
+    assert_eq!((arena.width, arena.height), (13, 9));
+    assert_eq!((arena.min_depth, arena.rotate, arena.mirror), (4, true, true));
+}
+
+#[test]
+fn parses_a_small_prefab() {
+    let text = "\
+name: closet
+weight: 3
+min_depth: 2
+max_depth: 5
+rotate: yes
+mirror: no
+---
+#=#
+#M#
+
+";
+    let prefab = Prefab::parse("file name", text).expect("prefab should parse");
+    assert_eq!(prefab.name, "closet");
+    assert_eq!((prefab.weight, prefab.min_depth, prefab.max_depth, prefab.rotate, prefab.mirror), (3, 2, 5, true, false));
+    assert_eq!((prefab.width, prefab.height), (3, 2));
+    assert_eq!(rows(&prefab), vec!["#=#", "#M#"]);
+}
+
+#[test]
+fn bad_header() {
+    assert_eq!(error_line("name: a\nsize: 3\n---\n#\n"), 2);
+    assert_eq!(error_line("weight: lots\n---\n#\n"), 1);
+    assert_eq!(error_line("name: a\nrotate: maybe\n---\n#\n"), 2);
+    assert_eq!(error_line("name: a\nthis isn't a key\n---\n#\n"), 2);
+}
+
+#[test]
+fn nothing_drawn() {
+    assert_eq!(error_line("name: a\n---\n\n"), 1);
+    assert_eq!(error_line("name: a\n"), 1);
+}
+
+#[test]
+fn unknown_symbol() {
+    assert_eq!(error_line("name: a\n---\n###\n#X#\n###\n"), 4);
+}
+
+#[test]
+fn ragged_rows() {
+    assert_eq!(error_line("name: a\n---\n####\n#..\n####\n"), 4);
+    assert_eq!(error_line("---\n###\n#.#\n####\n"), 4);
+}
+
+#[test]
+fn four_turns_come_back_round() {
+    let prefab = lopsided();
+    let turned = prefab.rotated().rotated().rotated().rotated();
+    assert_eq!((turned.width, turned.height), (prefab.width, prefab.height));
+    assert_eq!(rows(&turned), rows(&prefab));
+}
+
+#[test]
+fn two_flips_come_back_round() {
+    let prefab = lopsided();
+    let flipped = prefab.mirrored().mirrored();
+    assert_eq!(rows(&flipped), rows(&prefab));
+}
+
+#[test]
+fn a_turn_goes_clockwise() {
+    let turned = lopsided().rotated();
+    assert_eq!((turned.width, turned.height), (5, 4));
+    // the door on the right hand side ends up along the bottom
+    assert_eq!(rows(&turned), vec![
+        "#####",
+        ":#.M#",
+        "%~..#",
+        "###+#",
+    ]);
+}
+
+#[test]
+fn a_flip_goes_left_to_right() {
+    assert_eq!(rows(&lopsided().mirrored()), vec!["####", "+.M#", "#..#", "#~##", "#%:#"]);
+}
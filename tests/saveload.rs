@@ -0,0 +1,86 @@
+//! Saves seeded games and loads them back, checking nothing a replay would notice has changed, and that
+//! saves that can't be loaded are turned away without touching the game they were loaded into.
+
+use std::path::PathBuf;
+
+use my_engine::*;
+
+
+///somewhere to save to that no other test uses
+fn save_path(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("my_engine_{}_{}.json", name, std::process::id()))
+}
+
+///a seeded game a few turns in, so monsters have moved and the log has something in it
+fn seeded_game(seed: u64) -> State {
+    let mut gs = GameBuilder::new().with_seed(seed).build();
+    for action in [PlayerAction::Rest, PlayerAction::Move { dx: 1, dy: 0 }, PlayerAction::Move { dx: 0, dy: 1 }, PlayerAction::Rest] {
+        gs.play_turn(action);
+    }
+    gs
+}
+
+#[test]
+fn round_trip_keeps_the_world() {
+    for seed in [1, 7, 42] {
+        let path = save_path(&format!("round_trip_{}", seed));
+        let gs = seeded_game(seed);
+        let before = world_hash(&gs.ecs);
+        save_game(&gs.ecs, &path).expect("game should save");
+
+        let loaded = GameBuilder::new().load(&path).expect("save should load");
+        assert_eq!(world_hash(&loaded.ecs), before, "seed {}", seed);
+        std::fs::remove_file(&path).expect("save should be removable");
+    }
+}
+
+#[test]
+fn loading_over_a_game_replaces_it() {
+    let path = save_path("replace");
+    let saved = seeded_game(3);
+    save_game(&saved.ecs, &path).expect("game should save");
+
+    let mut other = seeded_game(4);
+    load_game(&mut other.ecs, &path).expect("save should load");
+    assert_eq!(*other.ecs.fetch::<Point>(), *saved.ecs.fetch::<Point>());
+    assert_eq!(other.ecs.fetch::<EventLog>().log, saved.ecs.fetch::<EventLog>().log);
+    assert_eq!(other.ecs.fetch::<Map>().revealed_tiles, saved.ecs.fetch::<Map>().revealed_tiles);
+    std::fs::remove_file(&path).expect("save should be removable");
+}
+
+#[test]
+fn wrong_version_is_refused() {
+    let path = save_path("version");
+    let gs = seeded_game(5);
+    save_game(&gs.ecs, &path).expect("game should save");
+    let mut save : serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    save["version"] = serde_json::json!(SAVE_VERSION + 1);
+    std::fs::write(&path, save.to_string()).unwrap();
+
+    match GameBuilder::new().load(&path) {
+        Err(SaveError::IncompatibleVersion { found, expected }) => {
+            assert_eq!(found, SAVE_VERSION + 1);
+            assert_eq!(expected, SAVE_VERSION);
+        }
+        Err(e) => panic!("expected IncompatibleVersion, got {}", e),
+        Ok(_) => panic!("expected IncompatibleVersion, the save loaded"),
+    }
+    std::fs::remove_file(&path).expect("save should be removable");
+}
+
+#[test]
+fn broken_save_leaves_the_game_alone() {
+    let path = save_path("broken");
+    let mut gs = seeded_game(6);
+    let before = world_hash(&gs.ecs);
+    save_game(&gs.ecs, &path).expect("game should save");
+    let mut save : serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+    save["more_components"] = serde_json::json!(5);
+    std::fs::write(&path, save.to_string()).unwrap();
+
+    assert!(matches!(load_game(&mut gs.ecs, &path), Err(SaveError::Format(_))));
+    assert_eq!(world_hash(&gs.ecs), before);
+    // still playable
+    gs.play_turn(PlayerAction::Rest);
+    std::fs::remove_file(&path).expect("save should be removable");
+}